@@ -0,0 +1,190 @@
+use std::{collections::VecDeque, time::Instant};
+
+use crate::{
+    config::ValidationConfig,
+    input_source::{Input, InputKind},
+};
+
+/// tells the inputs of someone at work apart from a bumped desk, a shaky
+/// touchpad or another program typing
+#[derive(Debug)]
+pub struct ActivityValidator {
+    config: ValidationConfig,
+    // mouse moves within the window as (when, dx, dy), oldest first
+    moves: VecDeque<(Instant, f64, f64)>,
+    // where the moves in the window add up to
+    moved: (f64, f64),
+}
+
+impl ActivityValidator {
+    pub fn new(config: ValidationConfig) -> Self {
+        Self {
+            config,
+            moves: VecDeque::new(),
+            moved: (0.0, 0.0),
+        }
+    }
+
+    /// whether the input, made at the given time, counts as activity
+    pub fn validate(&mut self, input: &Input, at: Instant) -> bool {
+        if input.synthetic && self.config.ignore_synthetic {
+            return false;
+        }
+        match input.kind {
+            InputKind::Key => true,
+            InputKind::Click => self.config.count_clicks,
+            InputKind::Scroll => self.config.count_scroll,
+            InputKind::MouseMove => self.validate_move(input.delta, at),
+        }
+    }
+
+    // a move counts once the mouse got far enough from where it was at the
+    // start of the window. jitter goes back and forth and never gets anywhere
+    fn validate_move(&mut self, (dx, dy): (f64, f64), at: Instant) -> bool {
+        let window = self.config.mouse_window();
+        while let Some(&(time, old_dx, old_dy)) = self.moves.front()
+            && at.saturating_duration_since(time) > window
+        {
+            self.moves.pop_front();
+            self.moved.0 -= old_dx;
+            self.moved.1 -= old_dy;
+        }
+        if self.moves.is_empty() {
+            // drops the rounding errors of all the subtractions
+            self.moved = (0.0, 0.0);
+        }
+
+        self.moves.push_back((at, dx, dy));
+        self.moved.0 += dx;
+        self.moved.1 += dy;
+        self.moves.len() >= self.config.min_mouse_events
+            && self.moved.0.hypot(self.moved.1) >= self.config.min_mouse_distance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn accept_all() -> ValidationConfig {
+        ValidationConfig {
+            min_mouse_distance: 0.0,
+            min_mouse_events: 0,
+            ignore_synthetic: false,
+            ..ValidationConfig::default()
+        }
+    }
+
+    /// feeds inputs recorded as (milliseconds since start, input) to a
+    /// validator and returns the ones it accepted, by their index
+    fn replay(config: ValidationConfig, recording: &[(u64, Input)]) -> Vec<usize> {
+        let start = Instant::now();
+        let mut validator = ActivityValidator::new(config);
+        recording
+            .iter()
+            .enumerate()
+            .filter(|(_, (ms, input))| {
+                validator.validate(input, start + Duration::from_millis(*ms))
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    fn moves(every_ms: u64, deltas: &[(f64, f64)]) -> Vec<(u64, Input)> {
+        deltas
+            .iter()
+            .enumerate()
+            .map(|(i, (dx, dy))| (i as u64 * every_ms, Input::mouse_move(*dx, *dy)))
+            .collect()
+    }
+
+    #[test]
+    fn a_bumped_desk_is_not_activity() {
+        // the mouse shakes a few pixels back and forth and settles
+        let recording = moves(
+            15,
+            &[
+                (2.0, 1.0),
+                (-3.0, -1.0),
+                (2.0, 2.0),
+                (-1.0, -2.0),
+                (1.0, 0.0),
+                (-1.0, 1.0),
+            ],
+        );
+        assert!(replay(ValidationConfig::default(), &recording).is_empty());
+    }
+
+    #[test]
+    fn moving_the_mouse_is_activity_once_it_got_somewhere() {
+        // a steady move to the right, 4px every 10ms
+        let recording = moves(10, &[(4.0, 0.0); 6]);
+        assert_eq!(
+            replay(ValidationConfig::default(), &recording),
+            [2, 3, 4, 5]
+        );
+    }
+
+    #[test]
+    fn a_single_jump_needs_more_moves() {
+        // the mouse got knocked over
+        let recording = moves(10, &[(0.0, 80.0)]);
+        assert!(replay(ValidationConfig::default(), &recording).is_empty());
+
+        let config = ValidationConfig {
+            min_mouse_events: 1,
+            ..ValidationConfig::default()
+        };
+        assert_eq!(replay(config, &recording), [0]);
+    }
+
+    #[test]
+    fn moves_outside_the_window_are_forgotten() {
+        // a slow drift of 3px every 600ms never adds up within the window
+        let recording = moves(600, &[(3.0, 0.0); 10]);
+        assert!(replay(ValidationConfig::default(), &recording).is_empty());
+
+        let config = ValidationConfig {
+            mouse_window_ms: 10_000,
+            ..ValidationConfig::default()
+        };
+        assert_eq!(replay(config, &recording), [3, 4, 5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn clicks_and_scrolls_count_on_their_own() {
+        let recording = [
+            (0, Input::new(InputKind::Click)),
+            (100, Input::new(InputKind::Scroll)),
+            (200, Input::new(InputKind::Scroll)),
+            (300, Input::new(InputKind::Key)),
+        ];
+        assert_eq!(
+            replay(ValidationConfig::default(), &recording),
+            [0, 1, 2, 3]
+        );
+
+        let config = ValidationConfig {
+            count_scroll: false,
+            ..ValidationConfig::default()
+        };
+        assert_eq!(replay(config, &recording), [0, 3]);
+        let config = ValidationConfig {
+            count_clicks: false,
+            ..ValidationConfig::default()
+        };
+        assert_eq!(replay(config, &recording), [1, 2, 3]);
+    }
+
+    #[test]
+    fn synthetic_input_is_ignored() {
+        let recording = [
+            (0, Input::new(InputKind::Key).with_synthetic(true)),
+            (10, Input::new(InputKind::Key)),
+            (20, Input::new(InputKind::Click).with_synthetic(true)),
+        ];
+        assert_eq!(replay(ValidationConfig::default(), &recording), [1]);
+        assert_eq!(replay(accept_all(), &recording), [0, 1, 2]);
+    }
+}
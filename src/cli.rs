@@ -0,0 +1,111 @@
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use crate::{export::Format, report};
+
+/// tracks the time spent on source files by watching file changes
+/// and keyboard/mouse activity
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// directory in which the database and the journal are kept
+    #[arg(long, global = true, env = "TIMEIT_DATA_DIR")]
+    pub data_dir: Option<PathBuf>,
+
+    /// user config file, defaults to ~/.config/timeit/config.toml
+    #[arg(long, global = true, env = "TIMEIT_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// what to log, e.g. debug or wakatime=trace,warn. takes the same
+    /// directives as RUST_LOG
+    #[arg(long, global = true, env = "TIMEIT_LOG", default_value = "info")]
+    pub log_level: String,
+
+    /// also write the log to this directory as json, a new file every day
+    #[arg(long, global = true, env = "TIMEIT_LOG_DIR")]
+    pub log_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// watch directories for source code changes and track time spent on them
+    Watch {
+        /// directories to watch recursively
+        #[arg(default_value = ".")]
+        dirs: Vec<PathBuf>,
+        /// show a live dashboard instead of printing what happens
+        #[arg(long)]
+        tui: bool,
+    },
+    /// show what the running watcher is tracking, the last entry and today's total
+    Status,
+    /// make the running watcher stop tracking until resumed
+    Pause,
+    /// make the running watcher track again after a pause
+    Resume,
+    /// make the running watcher read its config files again
+    Reload,
+    /// relay editor events written to stdin, one json object per line, to the running watcher
+    Editor,
+    /// show today's time by language, project and file
+    Today,
+    /// show tracked time for a day, a week, a month or a range of days.
+    /// defaults to today
+    Report {
+        /// a single day (yyyy-mm-dd)
+        #[arg(long, conflicts_with_all = ["week", "month", "from", "to"])]
+        day: Option<NaiveDate>,
+        /// an iso week (yyyy-Www), the current one if no week is given
+        #[arg(long, num_args = 0..=1, value_parser = report::parse_week, conflicts_with_all = ["month", "from", "to"])]
+        week: Option<Option<NaiveDate>>,
+        /// a month (yyyy-mm), the current one if no month is given
+        #[arg(long, num_args = 0..=1, value_parser = report::parse_month, conflicts_with_all = ["from", "to"])]
+        month: Option<Option<NaiveDate>>,
+        /// first day of the report (yyyy-mm-dd), defaults to today
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// last day of the report (yyyy-mm-dd), defaults to today
+        #[arg(long)]
+        to: Option<NaiveDate>,
+        /// how many languages, projects and files to show
+        #[arg(long, default_value_t = 5)]
+        top: usize,
+        /// write the report as a html page with charts to this file
+        #[arg(long, value_name = "FILE")]
+        html: Option<PathBuf>,
+    },
+    /// export the tracked entries or daily sums of a range of days as csv,
+    /// json or a markdown table
+    Export {
+        /// first day to export (yyyy-mm-dd), defaults to today
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// last day to export (yyyy-mm-dd), defaults to today
+        #[arg(long)]
+        to: Option<NaiveDate>,
+        #[arg(long, value_enum, default_value_t = Format::Csv)]
+        format: Format,
+        /// export a row per day instead of every entry. json always has both
+        #[arg(long)]
+        daily: bool,
+        /// only export entries of this project, can be given more than once
+        #[arg(long)]
+        project: Vec<String>,
+        /// only export entries of this language, can be given more than once
+        #[arg(long)]
+        language: Vec<String>,
+        /// file to write to instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// show where configuration and data are kept and the settings in use
+    Config {
+        /// write the default settings to the config file if it doesn't exist yet
+        #[arg(long)]
+        init: bool,
+    },
+}
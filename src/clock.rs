@@ -0,0 +1,92 @@
+use std::{
+    fmt::Debug,
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+
+#[cfg(test)]
+use std::{sync::Mutex, time::Duration};
+
+pub type SharedClock = Arc<dyn Clock>;
+
+/// source of the current time. everything that measures durations asks
+/// a clock instead of calling SystemTime::now()/Instant::now() itself,
+/// so tests can move time forward by hand
+pub trait Clock: Debug + Send + Sync {
+    /// wall clock time, used for the start and end of entries
+    fn now(&self) -> SystemTime;
+
+    /// monotonic time, used for intervals that must not jump with the wall clock
+    fn instant(&self) -> Instant;
+}
+
+/// the clock of the system
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl SystemClock {
+    pub fn shared() -> SharedClock {
+        Arc::new(Self)
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// a clock that only moves when it's told to. clones share the same time
+#[cfg(test)]
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    start: SystemTime,
+    start_instant: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    /// a clock standing still at a fixed, arbitrary point in time
+    pub fn new() -> Self {
+        Self {
+            start: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            start_instant: Instant::now(),
+            elapsed: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    pub fn shared(&self) -> SharedClock {
+        Arc::new(self.clone())
+    }
+
+    /// moves the clock forward
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock().unwrap() += by;
+    }
+
+    /// time that passed since the clock was created
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+
+    /// the wall clock time the mock clock started at
+    pub fn start(&self) -> SystemTime {
+        self.start
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        self.start + self.elapsed()
+    }
+
+    fn instant(&self) -> Instant {
+        self.start_instant + self.elapsed()
+    }
+}
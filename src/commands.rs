@@ -0,0 +1,214 @@
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local, NaiveDate};
+use std::{
+    io::{BufRead, Write},
+    os::unix::net::UnixStream,
+    path::Path,
+    time::Duration,
+};
+
+use crate::{
+    config::{Config, PROJECT_CONFIG_FILE},
+    daemon::{self, Request, StatusReply},
+    editor_protocol::{self, EditorEvent},
+    export::{Export, Filter, Format},
+    html_report,
+    journal::Journal,
+    manager::ManagerStatus,
+    report::{Period, Report},
+    stats::{TimeEntry, format_duration},
+    storage::Storage,
+};
+
+/// prints what the running watcher is tracking right now, the last saved
+/// entry and today's total
+pub fn status(data_dir: &Path) -> Result<()> {
+    let storage = Storage::open(&Storage::path_in(data_dir))?;
+
+    if daemon::is_running(data_dir) {
+        let status: StatusReply =
+            serde_json::from_value(daemon::request(data_dir, &Request::Status)?)?;
+        print_live_status(&status.manager);
+    } else {
+        println!("no watcher is running");
+        // the next watcher recovers whatever an interrupted one left unsaved
+        for entry in Journal::recover(&Journal::path_in(data_dir))? {
+            println!(
+                "unsaved: {} ({}) for {}",
+                entry.path.display(),
+                entry.language,
+                format_duration(entry.duration)
+            );
+        }
+    }
+
+    if let Some(entry) = storage.last_entry()? {
+        println!(
+            "last entry: {} ({}) for {}, ended {}",
+            entry.path.display(),
+            entry.language,
+            format_duration(entry.duration),
+            format_time(&entry)
+        );
+    }
+
+    let today = Local::now().date_naive();
+    let total = storage
+        .daily_stats(today, today)?
+        .first()
+        .map(|stats| stats.total_time)
+        .unwrap_or(Duration::ZERO);
+    println!("today: {}", format_duration(total));
+    Ok(())
+}
+
+/// asks the running watcher to stop tracking until resumed
+pub fn pause(data_dir: &Path) -> Result<()> {
+    daemon::request(data_dir, &Request::Pause)?;
+    println!("tracking paused, run resume to continue");
+    Ok(())
+}
+
+/// asks the running watcher to track again
+pub fn resume(data_dir: &Path) -> Result<()> {
+    daemon::request(data_dir, &Request::Resume)?;
+    println!("tracking resumed");
+    Ok(())
+}
+
+/// asks the running watcher to read its config files again
+pub fn reload(data_dir: &Path) -> Result<()> {
+    daemon::request(data_dir, &Request::Reload)?;
+    println!("config reloaded");
+    Ok(())
+}
+
+/// relays the editor events read from stdin to the running watcher. lines
+/// are checked first so that plugins see their mistakes on stderr
+pub fn editor(data_dir: &Path) -> Result<()> {
+    let path = editor_protocol::socket_path_in(data_dir);
+    let mut stream = UnixStream::connect(&path)
+        .with_context(|| format!("no watcher is running ({})", path.display()))?;
+
+    for line in std::io::stdin().lock().lines() {
+        let line = line.context("failed to read stdin")?;
+        match EditorEvent::parse(&line) {
+            Ok(Some(_)) => {
+                writeln!(stream, "{line}").context("the watcher went away")?;
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("skipped {line:?}: {e:#}"),
+        }
+    }
+    Ok(())
+}
+
+/// prints the breakdown of today
+pub fn today(data_dir: &Path) -> Result<()> {
+    report(data_dir, Period::Day(Local::now().date_naive()), 5, None)
+}
+
+/// prints the totals and top breakdowns of a period. with html, they are
+/// written to that file as a page with charts instead
+pub fn report(data_dir: &Path, period: Period, top: usize, html: Option<&Path>) -> Result<()> {
+    let storage = Storage::open(&Storage::path_in(data_dir))?;
+    let report = Report::build(&storage, period)?;
+    match html {
+        Some(path) => {
+            std::fs::write(path, html_report::render(&report, top))
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            println!(
+                "wrote the report of {} to {}",
+                period.title(),
+                path.display()
+            );
+        }
+        None => print!("{}", report.render(top)),
+    }
+    Ok(())
+}
+
+/// writes the entries of the range that pass the filter to the output file,
+/// or to stdout without one
+pub fn export(
+    data_dir: &Path,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    filter: Filter,
+    format: Format,
+    daily: bool,
+    output: Option<&Path>,
+) -> Result<()> {
+    let (from, to) = date_range(from, to)?;
+    let storage = Storage::open(&Storage::path_in(data_dir))?;
+    let export = Export::load(&storage, from, to, filter)?;
+    export.save(format, daily, output)?;
+
+    if let Some(output) = output {
+        println!(
+            "exported {} entries from {from} to {to} to {}",
+            export.entries.len(),
+            output.display()
+        );
+    }
+    Ok(())
+}
+
+/// prints where configuration and data are kept, followed by the settings in use.
+/// with init, the default settings are written to the config file first
+pub fn config(data_dir: &Path, config_path: &Path, init: bool) -> Result<()> {
+    if init {
+        if config_path.exists() {
+            bail!("{} already exists", config_path.display());
+        }
+        Config::default().write(config_path)?;
+        println!("wrote the default settings to {}", config_path.display());
+    }
+    let config = Config::load(config_path)?;
+
+    println!("config file:    {}", config_path.display());
+    println!("data directory: {}", data_dir.display());
+    println!("database:       {}", Storage::path_in(data_dir).display());
+    println!("journal:        {}", Journal::path_in(data_dir).display());
+    println!(
+        "projects can override the [watcher] and [languages] settings in their own {PROJECT_CONFIG_FILE}\n"
+    );
+    print!("{}", config.to_toml()?);
+    Ok(())
+}
+
+/// both ends of a range default to today
+fn date_range(from: Option<NaiveDate>, to: Option<NaiveDate>) -> Result<(NaiveDate, NaiveDate)> {
+    let today = Local::now().date_naive();
+    let (from, to) = (from.unwrap_or(today), to.unwrap_or(today));
+    if from > to {
+        bail!("--from ({from}) must not be after --to ({to})");
+    }
+    Ok((from, to))
+}
+
+fn print_live_status(status: &ManagerStatus) {
+    if status.paused {
+        println!("tracking is paused");
+    }
+    let active = status
+        .sessions
+        .iter()
+        .find(|session| status.active_file.as_ref() == Some(&session.path));
+    match active {
+        Some(session) if session.active => println!(
+            "tracking {} ({}) for {}",
+            session.path.display(),
+            session.language,
+            format_duration(Duration::from_secs(session.current_secs))
+        ),
+        Some(session) => println!("idle on {}", session.path.display()),
+        None => println!("not tracking anything right now"),
+    }
+}
+
+fn format_time(entry: &TimeEntry) -> String {
+    DateTime::<Local>::from(entry.end_time)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
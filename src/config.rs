@@ -0,0 +1,497 @@
+use anyhow::{Context, Result, bail, ensure};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::ErrorKind,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use toml::{Table, Value};
+
+use crate::{ignore_rules::validate_patterns, language::LanguageConfig};
+
+/// name of the per-project override file, looked up in every watched directory
+pub const PROJECT_CONFIG_FILE: &str = ".timeit.toml";
+
+/// sections that only make sense for the whole watcher, not for a single project
+const USER_ONLY_SECTIONS: [&str; 2] = ["input", "api"];
+
+/// every setting that can be changed without recompiling.
+/// the user config file can set any of them and a project's
+/// .timeit.toml can override the watcher and language settings for that project
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub watcher: WatcherConfig,
+    pub languages: LanguageConfig,
+    pub input: InputConfig,
+    pub api: ApiConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatcherConfig {
+    /// gitignore style patterns, applied at the root of every watched directory
+    /// with a lower precedence than the ignore files found in it
+    pub ignore_patterns: Vec<String>,
+    /// events on the same file closer than this are merged into one
+    pub debounce_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    /// no keyboard/mouse activity for this long means the user is idle
+    pub idle_threshold_secs: u64,
+    /// how often the idle state is checked
+    pub idle_check_interval_secs: u64,
+    /// where keyboard and mouse activity is read from
+    pub backend: InputBackend,
+    /// with backend = "command", a shell command that prints for how many
+    /// milliseconds the user has been idle, e.g. "xprintidle"
+    pub idle_command: Option<String>,
+    /// which inputs count as activity
+    pub validation: ValidationConfig,
+}
+
+/// rules against input that isn't the user at work. changes only apply
+/// after a restart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ValidationConfig {
+    /// the mouse has to get at least this many pixels away from where it
+    /// was mouse_window_ms ago, so a bumped desk doesn't count
+    pub min_mouse_distance: f64,
+    /// and it has to move at least this many times within the window
+    pub min_mouse_events: usize,
+    pub mouse_window_ms: u64,
+    /// input made up by other programs, e.g. through uinput. only the evdev
+    /// backend can tell it apart
+    pub ignore_synthetic: bool,
+    pub count_clicks: bool,
+    pub count_scroll: bool,
+}
+
+/// auto picks evdev under wayland and without a display, rdev otherwise.
+/// evdev needs read access to /dev/input/event*. logind and command don't
+/// see any input, they only ask the desktop whether the user is idle
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputBackend {
+    #[default]
+    Auto,
+    /// x11 only
+    Rdev,
+    Evdev,
+    /// the idle hint of the session, set by the desktop after its own timeout
+    Logind,
+    Command,
+}
+
+/// local http server that accepts heartbeats from wakatime editor plugins.
+/// point the plugins at it with `api_url = http://<address>/api/v1` in
+/// ~/.wakatime.cfg. changes only apply after a restart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiConfig {
+    pub enabled: bool,
+    /// address to listen on, keep it on localhost
+    pub address: String,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "127.0.0.1:9867".to_string(),
+        }
+    }
+}
+
+impl ApiConfig {
+    pub fn address(&self) -> Result<SocketAddr> {
+        self.address
+            .parse()
+            .with_context(|| format!("api.address: {:?} is not a valid address", self.address))
+    }
+}
+
+impl Default for WatcherConfig {
+    fn default() -> Self {
+        // gitignore style patterns, relative to the watched directory
+        let ignore_patterns = [
+            // Build artifacts
+            "target/",
+            "build/",
+            "dist/",
+            "out/",
+            ".next/",
+            // Dependencies
+            "node_modules/",
+            "vendor/",
+            ".venv/",
+            "venv/",
+            // IDE/Editor files
+            ".vscode/",
+            ".idea/",
+            ".vs/",
+            // Temporary files
+            ".tmp/",
+            // Cache directories
+            ".cache/",
+            "__pycache__/",
+            ".pytest_cache/",
+            // Log files
+            "*.log",
+        ];
+
+        Self {
+            ignore_patterns: ignore_patterns.map(String::from).to_vec(),
+            debounce_ms: 100,
+        }
+    }
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        Self {
+            idle_threshold_secs: 20,
+            idle_check_interval_secs: 20,
+            backend: InputBackend::Auto,
+            idle_command: None,
+            validation: ValidationConfig::default(),
+        }
+    }
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            min_mouse_distance: 10.0,
+            min_mouse_events: 3,
+            mouse_window_ms: 1000,
+            ignore_synthetic: true,
+            count_clicks: true,
+            count_scroll: true,
+        }
+    }
+}
+
+impl WatcherConfig {
+    pub fn debounce(&self) -> Duration {
+        Duration::from_millis(self.debounce_ms)
+    }
+}
+
+impl InputConfig {
+    pub fn idle_threshold(&self) -> Duration {
+        Duration::from_secs(self.idle_threshold_secs)
+    }
+}
+
+impl ValidationConfig {
+    pub fn mouse_window(&self) -> Duration {
+        Duration::from_millis(self.mouse_window_ms)
+    }
+}
+
+impl Config {
+    /// default location of the user config file
+    pub fn default_path() -> Result<PathBuf> {
+        let config_dir = dirs::config_dir().context("failed to find the user config directory")?;
+        Ok(config_dir.join("timeit").join("config.toml"))
+    }
+
+    /// loads the user config file. a missing file means every setting is at its default
+    pub fn load(path: &Path) -> Result<Self> {
+        let table = read_table(path)?.unwrap_or_default();
+        from_table(table).with_context(|| format!("invalid config in {}", path.display()))
+    }
+
+    /// returns the config to use for a watched project. settings in the
+    /// project's .timeit.toml take precedence over the ones of this config
+    pub fn for_project(&self, root: &Path) -> Result<Self> {
+        let path = root.join(PROJECT_CONFIG_FILE);
+        let Some(overrides) = read_table(&path)? else {
+            return Ok(self.clone());
+        };
+        if let Some(section) = USER_ONLY_SECTIONS
+            .iter()
+            .find(|section| overrides.contains_key(**section))
+        {
+            bail!(
+                "invalid config in {}: {section} settings can only be set in the user config",
+                path.display()
+            );
+        }
+
+        let mut table = Table::try_from(self).context("failed to serialize config")?;
+        merge(&mut table, overrides);
+        from_table(table).with_context(|| format!("invalid config in {}", path.display()))
+    }
+
+    /// checks the values that parse fine but make no sense
+    pub fn validate(&self) -> Result<()> {
+        self.languages.validate()?;
+        validate_patterns(&self.watcher.ignore_patterns)
+            .context("watcher.ignore_patterns is invalid")?;
+        ensure!(
+            self.watcher.debounce_ms <= 60_000,
+            "watcher.debounce_ms: {} is more than a minute",
+            self.watcher.debounce_ms
+        );
+        ensure!(
+            self.input.idle_threshold_secs > 0,
+            "input.idle_threshold_secs must be greater than 0"
+        );
+        ensure!(
+            self.input.idle_check_interval_secs > 0,
+            "input.idle_check_interval_secs must be greater than 0"
+        );
+        ensure!(
+            self.input.idle_check_interval_secs <= self.input.idle_threshold_secs,
+            "input.idle_check_interval_secs ({}) must not be greater than input.idle_threshold_secs ({})",
+            self.input.idle_check_interval_secs,
+            self.input.idle_threshold_secs
+        );
+        let validation = &self.input.validation;
+        ensure!(
+            validation.min_mouse_distance >= 0.0,
+            "input.validation.min_mouse_distance must not be negative"
+        );
+        ensure!(
+            validation.mouse_window_ms > 0,
+            "input.validation.mouse_window_ms must be greater than 0"
+        );
+        self.api.address()?;
+        Ok(())
+    }
+
+    /// writes this config to the given path, creating its directory if needed
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        std::fs::write(path, self.to_toml()?)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string_pretty(self).context("failed to serialize config")
+    }
+}
+
+/// reads a toml file, returns None if it doesn't exist
+fn read_table(path: &Path) -> Result<Option<Table>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+    let table = content
+        .parse::<Table>()
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+    Ok(Some(table))
+}
+
+fn from_table(table: Table) -> Result<Config> {
+    let config: Config = table.try_into()?;
+    config.validate()?;
+    Ok(config)
+}
+
+/// merges overrides into base. nested tables are merged key by key,
+/// any other value is replaced as a whole
+fn merge(base: &mut Table, overrides: Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(overrides)) => merge(base, overrides),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Result<Config> {
+        from_table(content.parse::<Table>().unwrap())
+    }
+
+    /// a directory no other test uses, emptied first
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("timeit-config-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn defaults_are_valid_and_survive_a_round_trip() {
+        let config = Config::default();
+        config.validate().unwrap();
+
+        let parsed = parse(&config.to_toml().unwrap()).unwrap();
+        assert_eq!(
+            parsed.watcher.ignore_patterns,
+            config.watcher.ignore_patterns
+        );
+        assert_eq!(parsed.input.idle_threshold_secs, 20);
+        assert_eq!(parsed.api.address, "127.0.0.1:9867");
+    }
+
+    #[test]
+    fn missing_files_leave_every_setting_at_its_default() {
+        let dir = temp_dir("missing");
+
+        let config = Config::load(&dir.join("config.toml")).unwrap();
+        assert_eq!(config.watcher.debounce_ms, 100);
+        let project = config.for_project(&dir).unwrap();
+        assert_eq!(project.watcher.debounce_ms, 100);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn values_that_make_no_sense_are_rejected() {
+        let cases = [
+            ("[watcher]\ndebounce_ms = 60001", "more than a minute"),
+            (
+                "[watcher]\nignore_patterns = [\"src/{a\"]",
+                "watcher.ignore_patterns is invalid",
+            ),
+            (
+                "[input]\nidle_threshold_secs = 0",
+                "idle_threshold_secs must be greater than 0",
+            ),
+            (
+                "[input]\nidle_check_interval_secs = 0",
+                "idle_check_interval_secs must be greater than 0",
+            ),
+            (
+                "[input]\nidle_check_interval_secs = 30",
+                "must not be greater than input.idle_threshold_secs (20)",
+            ),
+            (
+                "[input.validation]\nmin_mouse_distance = -1.0",
+                "min_mouse_distance must not be negative",
+            ),
+            (
+                "[input.validation]\nmouse_window_ms = 0",
+                "mouse_window_ms must be greater than 0",
+            ),
+            ("[api]\naddress = \"localhost\"", "is not a valid address"),
+            (
+                "[languages.extensions]\n\".rs\" = \"Rust\"",
+                "must be given without the dot",
+            ),
+            (
+                "[languages]\ntracked = [\"Cobol\"]",
+                "unknown language \"Cobol\"",
+            ),
+            // typos aren't silently ignored
+            ("[watcher]\ndebounce = 50", "unknown field `debounce`"),
+        ];
+        for (content, error) in cases {
+            let message = format!("{:#}", parse(content).unwrap_err());
+            assert!(message.contains(error), "{content:?} gave {message:?}");
+        }
+
+        // added languages can be tracked
+        parse("[languages]\ntracked = [\"Cobol\"]\n[languages.extensions]\ncbl = \"Cobol\"")
+            .unwrap();
+    }
+
+    #[test]
+    fn project_settings_win_and_the_rest_falls_back() {
+        let dir = temp_dir("project");
+        std::fs::write(
+            dir.join("config.toml"),
+            r#"
+[watcher]
+debounce_ms = 250
+ignore_patterns = ["target/", "*.log"]
+
+[languages.extensions]
+tpl = "Jinja"
+
+[input]
+idle_threshold_secs = 60
+"#,
+        )
+        .unwrap();
+        let project = dir.join("app");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(
+            project.join(PROJECT_CONFIG_FILE),
+            r#"
+[watcher]
+debounce_ms = 50
+
+[languages.extensions]
+mdx = "Markdown"
+"#,
+        )
+        .unwrap();
+
+        let user = Config::load(&dir.join("config.toml")).unwrap();
+        let config = user.for_project(&project).unwrap();
+
+        assert_eq!(config.watcher.debounce_ms, 50);
+        assert_eq!(config.watcher.ignore_patterns, ["target/", "*.log"]);
+        // tables are merged key by key
+        assert_eq!(config.languages.extensions["tpl"], "Jinja");
+        assert_eq!(config.languages.extensions["mdx"], "Markdown");
+        assert_eq!(config.input.idle_threshold_secs, 60);
+        // the user config itself is left alone
+        assert_eq!(user.watcher.debounce_ms, 250);
+
+        // lists are replaced as a whole
+        std::fs::write(
+            project.join(PROJECT_CONFIG_FILE),
+            "[watcher]\nignore_patterns = [\"dist/\"]\n",
+        )
+        .unwrap();
+        let config = user.for_project(&project).unwrap();
+        assert_eq!(config.watcher.ignore_patterns, ["dist/"]);
+        assert_eq!(config.watcher.debounce_ms, 250);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn projects_cannot_change_user_only_settings() {
+        let dir = temp_dir("user-only");
+        let user = Config::default();
+
+        for (content, section) in [
+            ("[input]\nidle_threshold_secs = 5\n", "input"),
+            ("[api]\nenabled = true\n", "api"),
+        ] {
+            std::fs::write(dir.join(PROJECT_CONFIG_FILE), content).unwrap();
+            let message = format!("{:#}", user.for_project(&dir).unwrap_err());
+            assert!(
+                message.contains(&format!(
+                    "{section} settings can only be set in the user config"
+                )),
+                "{message}"
+            );
+        }
+
+        // and their values are checked like the user's
+        std::fs::write(
+            dir.join(PROJECT_CONFIG_FILE),
+            "[watcher]\ndebounce_ms = 90000\n",
+        )
+        .unwrap();
+        let message = format!("{:#}", user.for_project(&dir).unwrap_err());
+        assert!(message.contains(PROJECT_CONFIG_FILE), "{message}");
+        assert!(message.contains("more than a minute"), "{message}");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,425 @@
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{DirBuilder, Permissions},
+    io::{BufRead, BufReader, Write},
+    os::unix::{
+        fs::{DirBuilderExt, PermissionsExt},
+        net::UnixStream,
+    },
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt},
+    net::UnixListener,
+    sync::{mpsc::UnboundedSender, oneshot},
+};
+use tracing::{debug, error, instrument, warn};
+
+use crate::{
+    manager::{ManagerCommand, ManagerStatus},
+    stats::DailyStats,
+    storage::Storage,
+};
+
+/// how long a client waits for the daemon to answer
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// a request to the daemon. every request is a single json object on its
+/// own line, e.g. `{"command": "stats", "date": "2024-05-01"}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    /// what is being tracked right now and today's total
+    Status,
+    /// every file session of the watcher
+    Sessions,
+    /// the breakdown of a day, defaults to today
+    Stats {
+        #[serde(default)]
+        date: Option<NaiveDate>,
+    },
+    Pause,
+    Resume,
+    /// reads the config files again and restarts the file watchers with them
+    Reload,
+}
+
+/// the answer to a request, also a single json object on its own line.
+/// data is only there if ok is true and error only if it's false
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Response {
+    fn ok(data: impl Serialize) -> Result<Self> {
+        Ok(Self {
+            ok: true,
+            data: Some(serde_json::to_value(data)?),
+            error: None,
+        })
+    }
+
+    fn error(error: impl std::fmt::Display) -> Self {
+        Self {
+            ok: false,
+            data: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+/// the answer to a status request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusReply {
+    #[serde(flatten)]
+    pub manager: ManagerStatus,
+    /// seconds saved for today, not counting the running session
+    pub today_secs: u64,
+}
+
+/// the answer to a stats request, durations are in seconds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatsReply {
+    pub date: String,
+    pub total_secs: u64,
+    pub languages: BTreeMap<String, u64>,
+    pub projects: BTreeMap<String, u64>,
+    pub files: BTreeMap<PathBuf, u64>,
+}
+
+impl StatsReply {
+    fn new(date: NaiveDate, stats: Option<DailyStats>) -> Self {
+        let stats = stats.unwrap_or_else(|| DailyStats::new(date.to_string()));
+        let secs = |duration: &Duration| duration.as_secs();
+        Self {
+            date: stats.date,
+            total_secs: stats.total_time.as_secs(),
+            languages: stats
+                .entries_by_lang
+                .iter()
+                .map(|(key, duration)| (key.clone(), secs(duration)))
+                .collect(),
+            projects: stats
+                .entries_by_project
+                .iter()
+                .map(|(key, duration)| (key.clone(), secs(duration)))
+                .collect(),
+            files: stats
+                .entries_by_file
+                .iter()
+                .map(|(key, duration)| (key.clone(), secs(duration)))
+                .collect(),
+        }
+    }
+}
+
+/// sent by the daemon to whoever owns the file watchers, which replies
+/// once the config was reloaded or with the reason it couldn't be
+pub type ReloadRequest = oneshot::Sender<Result<()>>;
+
+/// serves the control api of a running watcher on a unix socket, so that
+/// the cli, status bars and editor plugins can all talk to the same process
+#[derive(Debug)]
+pub struct Daemon {
+    manager: UnboundedSender<ManagerCommand>,
+    reload: UnboundedSender<ReloadRequest>,
+    // a connection of its own, rusqlite connections can't be shared
+    storage: Arc<Mutex<Storage>>,
+}
+
+impl Daemon {
+    pub fn new(
+        manager: UnboundedSender<ManagerCommand>,
+        reload: UnboundedSender<ReloadRequest>,
+        storage: Storage,
+    ) -> Self {
+        Self {
+            manager,
+            reload,
+            storage: Arc::new(Mutex::new(storage)),
+        }
+    }
+
+    /// location of the socket inside the given data directory
+    pub fn socket_path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("timeit.sock")
+    }
+
+    /// binds the socket, replacing one left behind by a watcher that died.
+    /// fails if another watcher is still listening on it
+    pub fn bind(path: &Path) -> Result<UnixListener> {
+        bind_socket(path)
+    }
+
+    /// accepts connections forever. every connection can send any number of requests
+    pub async fn serve(self: Arc<Self>, listener: UnixListener) {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let daemon = self.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = daemon.handle_connection(stream).await {
+                            warn!("daemon connection failed: {e:#}");
+                        }
+                    });
+                }
+                Err(e) => error!("failed to accept daemon connection: {e}"),
+            }
+        }
+    }
+
+    /// answers every line of the connection until the client hangs up
+    #[instrument(name = "daemon", skip_all)]
+    async fn handle_connection(&self, stream: tokio::net::UnixStream) -> Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = tokio::io::BufReader::new(reader).lines();
+
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => {
+                    debug!(?request, "daemon request");
+                    self.handle_request(request)
+                        .await
+                        .unwrap_or_else(|e| Response::error(format!("{e:#}")))
+                }
+                Err(e) => Response::error(format!("invalid request: {e}")),
+            };
+            let mut line = serde_json::to_vec(&response)?;
+            line.push(b'\n');
+            writer.write_all(&line).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_request(&self, request: Request) -> Result<Response> {
+        match request {
+            Request::Status => {
+                let manager = self.manager_status().await?;
+                let today = Local::now().date_naive();
+                let today_secs = self
+                    .daily_stats(today)
+                    .await?
+                    .map(|stats| stats.total_time.as_secs())
+                    .unwrap_or(0);
+                Response::ok(StatusReply {
+                    manager,
+                    today_secs,
+                })
+            }
+            Request::Sessions => Response::ok(self.manager_status().await?.sessions),
+            Request::Stats { date } => {
+                let date = date.unwrap_or_else(|| Local::now().date_naive());
+                Response::ok(StatsReply::new(date, self.daily_stats(date).await?))
+            }
+            Request::Pause => {
+                self.send(ManagerCommand::Pause)?;
+                Response::ok(())
+            }
+            Request::Resume => {
+                self.send(ManagerCommand::Resume)?;
+                Response::ok(())
+            }
+            Request::Reload => {
+                let (reply, result) = oneshot::channel();
+                self.reload
+                    .send(reply)
+                    .map_err(|_| anyhow!("the watcher is shutting down"))?;
+                result
+                    .await
+                    .context("the watcher is shutting down")?
+                    .context("failed to reload the config")?;
+                Response::ok(())
+            }
+        }
+    }
+
+    async fn manager_status(&self) -> Result<ManagerStatus> {
+        let (reply, status) = oneshot::channel();
+        self.send(ManagerCommand::Status(reply))?;
+        status.await.context("the manager is shutting down")
+    }
+
+    fn send(&self, command: ManagerCommand) -> Result<()> {
+        self.manager
+            .send(command)
+            .map_err(|_| anyhow!("the manager is shutting down"))
+    }
+
+    /// sqlite is blocking, so the query runs off the async workers
+    async fn daily_stats(&self, date: NaiveDate) -> Result<Option<DailyStats>> {
+        let storage = self.storage.clone();
+        tokio::task::spawn_blocking(move || {
+            let storage = storage.lock().expect("failed to get a lock on storage");
+            Ok(storage.daily_stats(date, date)?.into_iter().next())
+        })
+        .await?
+    }
+}
+
+/// binds a unix socket only the user can connect to, replacing one left
+/// behind by a watcher that died. fails if something is still listening on it
+pub fn bind_socket(path: &Path) -> Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            bail!("another watcher is already running ({})", path.display());
+        }
+        std::fs::remove_file(path)
+            .with_context(|| format!("failed to remove stale socket {}", path.display()))?;
+    }
+
+    // the socket is bound in a directory only the user can enter and only
+    // linked into place once its permissions are right, so nobody else can
+    // ever connect to it. unlike a rename, the link fails if another watcher
+    // got there first
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("invalid socket path {}", path.display()))?;
+    let private_dir = path.with_file_name(format!(
+        ".{}.{}",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&private_dir);
+    DirBuilder::new()
+        .mode(0o700)
+        .create(&private_dir)
+        .with_context(|| format!("failed to create {}", private_dir.display()))?;
+    let listener = bind_privately(&private_dir.join(name), path);
+    let _ = std::fs::remove_dir_all(&private_dir);
+    listener
+}
+
+fn bind_privately(private_path: &Path, path: &Path) -> Result<UnixListener> {
+    let listener = UnixListener::bind(private_path)
+        .with_context(|| format!("failed to listen on {}", path.display()))?;
+    std::fs::set_permissions(private_path, Permissions::from_mode(0o600))
+        .with_context(|| format!("failed to set permissions of {}", path.display()))?;
+    std::fs::hard_link(private_path, path)
+        .with_context(|| format!("failed to listen on {}", path.display()))?;
+    Ok(listener)
+}
+
+/// checks whether a watcher is listening in the given data directory
+pub fn is_running(data_dir: &Path) -> bool {
+    UnixStream::connect(Daemon::socket_path_in(data_dir)).is_ok()
+}
+
+/// sends a single request to the watcher running in the given data
+/// directory and returns the data of its answer
+pub fn request(data_dir: &Path, request: &Request) -> Result<serde_json::Value> {
+    let path = Daemon::socket_path_in(data_dir);
+    let mut stream = UnixStream::connect(&path)
+        .with_context(|| format!("no watcher is running ({})", path.display()))?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    stream.write_all(&line).context("failed to send request")?;
+
+    let mut answer = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut answer)
+        .context("failed to read answer")?;
+    let response: Response =
+        serde_json::from_str(&answer).context("the watcher sent an invalid answer")?;
+    if !response.ok {
+        bail!(
+            "{}",
+            response
+                .error
+                .unwrap_or_else(|| "request failed".to_string())
+        );
+    }
+    Ok(response.data.unwrap_or(serde_json::Value::Null))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn requests_are_tagged_by_their_command() {
+        let date = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let cases = [
+            (Request::Status, json!({"command": "status"})),
+            (Request::Sessions, json!({"command": "sessions"})),
+            (
+                Request::Stats { date: Some(date) },
+                json!({"command": "stats", "date": "2024-05-01"}),
+            ),
+            (Request::Pause, json!({"command": "pause"})),
+            (Request::Resume, json!({"command": "resume"})),
+            (Request::Reload, json!({"command": "reload"})),
+        ];
+        for (request, expected) in cases {
+            let value = serde_json::to_value(&request).unwrap();
+            assert_eq!(value, expected, "{request:?}");
+            let parsed: Request = serde_json::from_value(value).unwrap();
+            assert_eq!(
+                serde_json::to_value(&parsed).unwrap(),
+                expected,
+                "{request:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn stats_default_to_no_date() {
+        let request: Request = serde_json::from_str(r#"{"command": "stats"}"#).unwrap();
+        assert!(matches!(request, Request::Stats { date: None }));
+        assert!(serde_json::from_str::<Request>(r#"{"command": "nonsense"}"#).is_err());
+    }
+
+    #[test]
+    fn responses_have_either_data_or_an_error() {
+        let ok = serde_json::to_value(Response::ok(42).unwrap()).unwrap();
+        assert_eq!(ok, json!({"ok": true, "data": 42}));
+        let error = serde_json::to_value(Response::error("no such day")).unwrap();
+        assert_eq!(error, json!({"ok": false, "error": "no such day"}));
+
+        let parsed: Response = serde_json::from_value(error).unwrap();
+        assert!(!parsed.ok);
+        assert!(parsed.data.is_none());
+        assert_eq!(parsed.error.as_deref(), Some("no such day"));
+    }
+
+    #[tokio::test]
+    async fn stale_sockets_are_replaced_and_live_ones_kept() {
+        let dir = std::env::temp_dir().join(format!("timeit-daemon-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = Daemon::socket_path_in(&dir);
+
+        // left behind by a watcher that died
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let listener = bind_socket(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(is_running(&dir));
+        // nothing is left of the private directory
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        let error = bind_socket(&path).unwrap_err().to_string();
+        assert!(error.contains("already running"), "{error}");
+        assert!(is_running(&dir));
+
+        drop(listener);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
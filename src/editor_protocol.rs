@@ -0,0 +1,300 @@
+//! a newline delimited json protocol for editor plugins to tell the watcher
+//! exactly which file is being looked at.
+//!
+//! plugins connect to `editor.sock` in the data directory (the same directory
+//! that holds `timeit.sock`) and write one json object per line. they can also
+//! start `timeit editor` and write the same lines to its stdin, which relays
+//! them to the running watcher. nothing is ever written back, lines that
+//! can't be understood are reported by the watcher and skipped.
+//!
+//! every message has an `event` and the absolute `path` of the file:
+//!
+//! ```text
+//! {"event": "focus", "path": "/home/me/app/src/main.rs", "language": "Rust", "project": "app"}
+//! {"event": "activity", "path": "/home/me/app/src/main.rs"}
+//! {"event": "save", "path": "/home/me/app/src/main.rs"}
+//! {"event": "close", "path": "/home/me/app/src/main.rs"}
+//! ```
+//!
+//! - `focus`: a buffer of the file was entered. `language` and `project` are
+//!   optional and are detected from the path when left out
+//! - `activity`: the cursor moved or text was typed in the buffer. sending it
+//!   at most every few seconds is enough
+//! - `save`: the buffer was written to disk. takes the same optional fields as focus
+//! - `close`: the buffer was closed, which ends the file's session
+//!
+//! unknown fields are ignored so that plugins can send more than this version
+//! understands. new events and fields will only ever be added, never changed
+
+use anyhow::{Context, Result, ensure};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::{
+    io::AsyncBufReadExt,
+    net::{UnixListener, UnixStream},
+    sync::mpsc::UnboundedSender,
+};
+use tracing::{error, info, instrument, warn};
+
+use crate::tracking_event::TrackingEvents;
+
+/// a single line of the protocol
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum EditorEvent {
+    Focus {
+        path: PathBuf,
+        #[serde(default)]
+        language: Option<String>,
+        #[serde(default)]
+        project: Option<String>,
+    },
+    Activity {
+        path: PathBuf,
+    },
+    Save {
+        path: PathBuf,
+        #[serde(default)]
+        language: Option<String>,
+        #[serde(default)]
+        project: Option<String>,
+    },
+    Close {
+        path: PathBuf,
+    },
+}
+
+impl EditorEvent {
+    /// parses a line of the protocol. blank lines give None
+    pub fn parse(line: &str) -> Result<Option<Self>> {
+        if line.trim().is_empty() {
+            return Ok(None);
+        }
+        let event: Self = serde_json::from_str(line).context("invalid editor event")?;
+        ensure!(
+            event.path().is_absolute(),
+            "path {} of editor event must be absolute",
+            event.path().display()
+        );
+        Ok(Some(event))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EditorEvent::Focus { .. } => "focus",
+            EditorEvent::Activity { .. } => "activity",
+            EditorEvent::Save { .. } => "save",
+            EditorEvent::Close { .. } => "close",
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            EditorEvent::Focus { path, .. }
+            | EditorEvent::Activity { path }
+            | EditorEvent::Save { path, .. }
+            | EditorEvent::Close { path } => path,
+        }
+    }
+
+    /// turns the editor event into the tracking event the manager expects
+    pub fn into_tracking_event(self) -> TrackingEvents {
+        match self {
+            EditorEvent::Focus {
+                path,
+                language,
+                project,
+            } => TrackingEvents::FileFocused {
+                path: path.to_string_lossy().to_string(),
+                language,
+                project,
+            },
+            // activity in a buffer also focuses its file, in case the
+            // focus event was missed
+            EditorEvent::Activity { path } => TrackingEvents::FileFocused {
+                path: path.to_string_lossy().to_string(),
+                language: None,
+                project: None,
+            },
+            EditorEvent::Save {
+                path,
+                language,
+                project,
+            } => TrackingEvents::FileModified {
+                path: path.to_string_lossy().to_string(),
+                language,
+                project,
+            },
+            EditorEvent::Close { path } => TrackingEvents::BufferClosed {
+                path: path.to_string_lossy().to_string(),
+            },
+        }
+    }
+}
+
+/// location of the editor socket inside the given data directory
+pub fn socket_path_in(data_dir: &Path) -> PathBuf {
+    data_dir.join("editor.sock")
+}
+
+/// accepts editor connections forever and feeds their events to the manager
+pub async fn serve(listener: UnixListener, tracking_sender: UnboundedSender<TrackingEvents>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(handle_connection(stream, tracking_sender.clone()));
+            }
+            Err(e) => error!("failed to accept editor connection: {e}"),
+        }
+    }
+}
+
+/// reads events until the editor hangs up
+#[instrument(name = "editor", skip_all)]
+async fn handle_connection(stream: UnixStream, tracking_sender: UnboundedSender<TrackingEvents>) {
+    let mut lines = tokio::io::BufReader::new(stream).lines();
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("editor connection failed: {e}");
+                return;
+            }
+        };
+        match EditorEvent::parse(&line) {
+            Ok(Some(event)) => {
+                info!("editor {}: {}", event.name(), event.path().display());
+                if tracking_sender.send(event.into_tracking_event()).is_err() {
+                    // manager is gone, nothing left to report to
+                    return;
+                }
+            }
+            Ok(None) => {}
+            Err(e) => warn!("skipped editor line {line:?}: {e:#}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    const MAIN: &str = "/home/me/app/src/main.rs";
+
+    fn parse(line: &str) -> EditorEvent {
+        EditorEvent::parse(line).unwrap().unwrap()
+    }
+
+    #[test]
+    fn documented_lines_parse() {
+        assert_eq!(
+            parse(
+                r#"{"event": "focus", "path": "/home/me/app/src/main.rs", "language": "Rust", "project": "app"}"#
+            ),
+            EditorEvent::Focus {
+                path: PathBuf::from(MAIN),
+                language: Some("Rust".to_string()),
+                project: Some("app".to_string()),
+            }
+        );
+        assert_eq!(
+            parse(r#"{"event": "activity", "path": "/home/me/app/src/main.rs"}"#),
+            EditorEvent::Activity {
+                path: PathBuf::from(MAIN)
+            }
+        );
+        assert_eq!(
+            parse(r#"{"event": "save", "path": "/home/me/app/src/main.rs"}"#),
+            EditorEvent::Save {
+                path: PathBuf::from(MAIN),
+                language: None,
+                project: None,
+            }
+        );
+        assert_eq!(
+            parse(r#"{"event": "close", "path": "/home/me/app/src/main.rs"}"#),
+            EditorEvent::Close {
+                path: PathBuf::from(MAIN)
+            }
+        );
+    }
+
+    #[test]
+    fn blank_lines_are_skipped() {
+        assert_eq!(EditorEvent::parse("").unwrap(), None);
+        assert_eq!(EditorEvent::parse("  \t").unwrap(), None);
+    }
+
+    #[test]
+    fn unknown_fields_are_ignored() {
+        assert_eq!(
+            parse(r#"{"event": "close", "path": "/home/me/app/src/main.rs", "cursor": [3, 14]}"#),
+            EditorEvent::Close {
+                path: PathBuf::from(MAIN)
+            }
+        );
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        let lines = [
+            r#"{"event": "focus", "path": "src/main.rs"}"#,
+            r#"{"event": "save", "path": "./main.rs"}"#,
+            r#"{"event": "scroll", "path": "/home/me/app/src/main.rs"}"#,
+            r#"{"event": "focus"}"#,
+            r#"{"path": "/home/me/app/src/main.rs"}"#,
+            "focus /home/me/app/src/main.rs",
+        ];
+        for line in lines {
+            assert!(EditorEvent::parse(line).is_err(), "{line}");
+        }
+    }
+
+    #[test]
+    fn events_become_tracking_events() {
+        let event = parse(r#"{"event": "focus", "path": "/a.rs", "language": "Rust"}"#);
+        assert!(matches!(
+            event.into_tracking_event(),
+            TrackingEvents::FileFocused { path, language: Some(language), project: None }
+                if path == "/a.rs" && language == "Rust"
+        ));
+        let event = parse(r#"{"event": "activity", "path": "/a.rs"}"#);
+        assert!(matches!(
+            event.into_tracking_event(),
+            TrackingEvents::FileFocused { path, language: None, project: None } if path == "/a.rs"
+        ));
+        let event = parse(r#"{"event": "save", "path": "/a.rs", "project": "app"}"#);
+        assert!(matches!(
+            event.into_tracking_event(),
+            TrackingEvents::FileModified { path, language: None, project: Some(project) }
+                if path == "/a.rs" && project == "app"
+        ));
+        let event = parse(r#"{"event": "close", "path": "/a.rs"}"#);
+        assert!(matches!(
+            event.into_tracking_event(),
+            TrackingEvents::BufferClosed { path } if path == "/a.rs"
+        ));
+    }
+
+    #[tokio::test]
+    async fn bad_lines_do_not_end_the_connection() {
+        let (mut editor, watcher) = UnixStream::pair().unwrap();
+        let (tracking_tx, mut tracking_rx) = tokio::sync::mpsc::unbounded_channel();
+        let connection = tokio::spawn(handle_connection(watcher, tracking_tx));
+
+        editor
+            .write_all(b"not json\n\n{\"event\": \"save\", \"path\": \"/a.rs\"}\n")
+            .await
+            .unwrap();
+        drop(editor);
+        connection.await.unwrap();
+
+        assert!(matches!(
+            tracking_rx.recv().await,
+            Some(TrackingEvents::FileModified { path, .. }) if path == "/a.rs"
+        ));
+        assert!(tracking_rx.recv().await.is_none());
+    }
+}
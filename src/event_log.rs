@@ -0,0 +1,85 @@
+use chrono::{DateTime, Local};
+use std::{
+    collections::VecDeque,
+    fmt::{Debug, Write},
+    sync::Mutex,
+};
+use tracing::{
+    Event, Level, Subscriber,
+    field::{Field, Visit},
+};
+use tracing_subscriber::{Layer, layer::Context};
+
+/// how many lines are kept for the dashboard
+const CAPACITY: usize = 500;
+
+/// a line of the event log
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub time: DateTime<Local>,
+    pub level: Level,
+    pub message: String,
+}
+
+// filled by EventLogLayer, which takes the place of printing to the
+// terminal while the dashboard is drawn
+static LINES: Mutex<VecDeque<LogLine>> = Mutex::new(VecDeque::new());
+
+/// a tracing layer that keeps the latest events for lines()
+#[derive(Debug, Default)]
+pub struct EventLogLayer;
+
+impl<S: Subscriber> Layer<S> for EventLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut message = Message::default();
+        event.record(&mut message);
+        push(LogLine {
+            time: Local::now(),
+            level: *event.metadata().level(),
+            message: message.text + &message.fields,
+        });
+    }
+}
+
+/// the message of an event followed by its other fields as key=value
+#[derive(Debug, Default)]
+struct Message {
+    text: String,
+    fields: String,
+}
+
+impl Visit for Message {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.text = value.to_string();
+        } else {
+            let _ = write!(self.fields, " {}={value}", field.name());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.text = format!("{value:?}");
+        } else {
+            let _ = write!(self.fields, " {}={value:?}", field.name());
+        }
+    }
+}
+
+fn push(line: LogLine) {
+    let mut lines = LINES.lock().expect("failed to get a lock on the event log");
+    if lines.len() == CAPACITY {
+        lines.pop_front();
+    }
+    lines.push_back(line);
+}
+
+/// the kept lines, oldest first
+pub fn lines() -> Vec<LogLine> {
+    LINES
+        .lock()
+        .expect("failed to get a lock on the event log")
+        .iter()
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,389 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    hash::Hash,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    stats::{DailyStats, TimeEntry, format_duration, group_by_day},
+    storage::Storage,
+};
+
+/// version of the json export. only ever raised when fields are removed or
+/// change their meaning, new fields can be added without it
+pub const EXPORT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Csv,
+    Json,
+    Markdown,
+}
+
+/// which entries to export. empty lists let everything through
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Filter {
+    pub projects: Vec<String>,
+    pub languages: Vec<String>,
+}
+
+impl Filter {
+    /// names are compared ignoring case, entries without a project only
+    /// pass if no project was asked for
+    pub fn matches(&self, entry: &TimeEntry) -> bool {
+        let project = self.projects.is_empty()
+            || entry.project.as_ref().is_some_and(|project| {
+                self.projects
+                    .iter()
+                    .any(|wanted| wanted.eq_ignore_ascii_case(project))
+            });
+        let language = self.languages.is_empty()
+            || self
+                .languages
+                .iter()
+                .any(|wanted| wanted.eq_ignore_ascii_case(&entry.language));
+        project && language
+    }
+}
+
+/// the entries of a range of days and their daily sums
+#[derive(Debug, Clone)]
+pub struct Export {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub filter: Filter,
+    pub entries: Vec<TimeEntry>,
+    /// days with tracked time only, oldest first
+    pub days: Vec<DailyStats>,
+}
+
+/// the json export. field names are part of the schema, see EXPORT_VERSION
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportDocument {
+    pub version: u32,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub filter: Filter,
+    pub entries: Vec<EntryRecord>,
+    pub days: Vec<DayRecord>,
+}
+
+/// a TimeEntry with rfc 3339 times and whole seconds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryRecord {
+    pub path: PathBuf,
+    pub language: String,
+    pub project: Option<String>,
+    pub start_time: String,
+    pub end_time: String,
+    pub duration_secs: u64,
+}
+
+/// a DailyStats in seconds, with sorted keys so exports can be diffed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DayRecord {
+    pub date: String,
+    pub total_secs: u64,
+    pub languages: BTreeMap<String, u64>,
+    pub projects: BTreeMap<String, u64>,
+    pub files: BTreeMap<PathBuf, u64>,
+}
+
+impl From<&TimeEntry> for EntryRecord {
+    fn from(entry: &TimeEntry) -> Self {
+        Self {
+            path: entry.path.clone(),
+            language: entry.language.clone(),
+            project: entry.project.clone(),
+            start_time: rfc3339(entry.start_time),
+            end_time: rfc3339(entry.end_time),
+            duration_secs: entry.duration.as_secs(),
+        }
+    }
+}
+
+impl From<&DailyStats> for DayRecord {
+    fn from(day: &DailyStats) -> Self {
+        Self {
+            date: day.date.clone(),
+            total_secs: day.total_time.as_secs(),
+            languages: in_secs(&day.entries_by_lang),
+            projects: in_secs(&day.entries_by_project),
+            files: in_secs(&day.entries_by_file),
+        }
+    }
+}
+
+impl Export {
+    /// reads the entries between from and to (both inclusive) that pass the filter
+    pub fn load(storage: &Storage, from: NaiveDate, to: NaiveDate, filter: Filter) -> Result<Self> {
+        let mut entries = storage.entries_for_days(from, to)?;
+        entries.retain(|entry| filter.matches(entry));
+        Ok(Self::new(from, to, filter, entries))
+    }
+
+    pub fn new(from: NaiveDate, to: NaiveDate, filter: Filter, entries: Vec<TimeEntry>) -> Self {
+        let days = group_by_day(&entries);
+        Self {
+            from,
+            to,
+            filter,
+            entries,
+            days,
+        }
+    }
+
+    /// writes the export to the file, or to stdout without one
+    pub fn save(&self, format: Format, daily: bool, path: Option<&Path>) -> Result<()> {
+        match path {
+            Some(path) => {
+                let file = File::create(path)
+                    .with_context(|| format!("failed to create {}", path.display()))?;
+                let mut out = BufWriter::new(file);
+                self.write(format, daily, &mut out)?;
+                out.flush()
+                    .with_context(|| format!("failed to write {}", path.display()))
+            }
+            None => self.write(format, daily, &mut std::io::stdout().lock()),
+        }
+    }
+
+    /// csv and markdown hold either the entries or, with daily, the days.
+    /// json always holds both
+    pub fn write(&self, format: Format, daily: bool, out: &mut impl Write) -> Result<()> {
+        match (format, daily) {
+            (Format::Csv, false) => self.write_entries_csv(out)?,
+            (Format::Csv, true) => self.write_days_csv(out)?,
+            (Format::Json, _) => {
+                serde_json::to_writer_pretty(&mut *out, &self.document())?;
+                writeln!(out)?;
+            }
+            (Format::Markdown, false) => self.write_entries_markdown(out)?,
+            (Format::Markdown, true) => self.write_days_markdown(out)?,
+        }
+        Ok(())
+    }
+
+    pub fn document(&self) -> ExportDocument {
+        ExportDocument {
+            version: EXPORT_VERSION,
+            from: self.from,
+            to: self.to,
+            filter: self.filter.clone(),
+            entries: self.entries.iter().map(EntryRecord::from).collect(),
+            days: self.days.iter().map(DayRecord::from).collect(),
+        }
+    }
+
+    fn write_entries_csv(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(
+            out,
+            "path,language,project,start_time,end_time,duration_secs"
+        )?;
+        for entry in self.entries.iter().map(EntryRecord::from) {
+            writeln!(
+                out,
+                "{},{},{},{},{},{}",
+                csv_field(&entry.path.display().to_string()),
+                csv_field(&entry.language),
+                csv_field(entry.project.as_deref().unwrap_or("")),
+                entry.start_time,
+                entry.end_time,
+                entry.duration_secs
+            )?;
+        }
+        Ok(())
+    }
+
+    /// one row per day and name, the day's total has the group "total" and no name
+    fn write_days_csv(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(out, "date,group,name,duration_secs")?;
+        for day in self.days.iter().map(DayRecord::from) {
+            writeln!(out, "{},total,,{}", day.date, day.total_secs)?;
+            for (name, secs) in &day.languages {
+                writeln!(out, "{},language,{},{secs}", day.date, csv_field(name))?;
+            }
+            for (name, secs) in &day.projects {
+                writeln!(out, "{},project,{},{secs}", day.date, csv_field(name))?;
+            }
+            for (path, secs) in &day.files {
+                let name = path.display().to_string();
+                writeln!(out, "{},file,{},{secs}", day.date, csv_field(&name))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_entries_markdown(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(
+            out,
+            "| Start | End | Duration | Language | Project | File |"
+        )?;
+        writeln!(out, "| --- | --- | ---: | --- | --- | --- |")?;
+        for entry in &self.entries {
+            writeln!(
+                out,
+                "| {} | {} | {} | {} | {} | {} |",
+                local_time(entry.start_time),
+                local_time(entry.end_time),
+                format_duration(entry.duration),
+                markdown_cell(&entry.language),
+                markdown_cell(entry.project.as_deref().unwrap_or("")),
+                markdown_cell(&entry.path.display().to_string())
+            )?;
+        }
+        Ok(())
+    }
+
+    /// one row per day with its languages and projects, longest first
+    fn write_days_markdown(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(out, "| Date | Total | Languages | Projects |")?;
+        writeln!(out, "| --- | ---: | --- | --- |")?;
+        for day in &self.days {
+            writeln!(
+                out,
+                "| {} | {} | {} | {} |",
+                day.date,
+                format_duration(day.total_time),
+                markdown_cell(&summary(&day.entries_by_lang)),
+                markdown_cell(&summary(&day.entries_by_project))
+            )?;
+        }
+        let total: Duration = self.days.iter().map(|day| day.total_time).sum();
+        writeln!(out, "| **Total** | **{}** | | |", format_duration(total))
+    }
+}
+
+fn in_secs<K: Ord + Clone>(durations: &HashMap<K, Duration>) -> BTreeMap<K, u64> {
+    durations
+        .iter()
+        .map(|(key, duration)| (key.clone(), duration.as_secs()))
+        .collect()
+}
+
+/// e.g. "Rust 1h 05m, Lua 4m 10s"
+fn summary<K: Eq + Hash + AsRef<str>>(durations: &HashMap<K, Duration>) -> String {
+    let mut durations: Vec<_> = durations.iter().collect();
+    durations.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.as_ref().cmp(b.0.as_ref())));
+    durations
+        .iter()
+        .map(|(name, duration)| format!("{} {}", name.as_ref(), format_duration(**duration)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn rfc3339(time: SystemTime) -> String {
+    DateTime::<Local>::from(time).to_rfc3339()
+}
+
+fn local_time(time: SystemTime) -> String {
+    DateTime::<Local>::from(time)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+/// quotes fields that contain separators, quotes or line breaks
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// pipes would end the cell and line breaks the row
+fn markdown_cell(value: &str) -> String {
+    value.replace('|', "\\|").replace(['\n', '\r'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, language: &str, project: Option<&str>, secs: u64) -> TimeEntry {
+        let start_time = SystemTime::now();
+        TimeEntry {
+            path: PathBuf::from(path),
+            language: language.to_string(),
+            project: project.map(str::to_string),
+            duration: Duration::from_secs(secs),
+            start_time,
+            end_time: start_time + Duration::from_secs(secs),
+        }
+    }
+
+    fn export(filter: Filter, entries: Vec<TimeEntry>) -> Export {
+        let today = Local::now().date_naive();
+        Export::new(today, today, filter, entries)
+    }
+
+    fn written(export: &Export, format: Format, daily: bool) -> String {
+        let mut out = Vec::new();
+        export.write(format, daily, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn filter_ignores_case_and_drops_entries_without_project() {
+        let filter = Filter {
+            projects: vec!["TimeIT".to_string()],
+            languages: vec!["rust".to_string()],
+        };
+        assert!(filter.matches(&entry("/a.rs", "Rust", Some("timeit"), 1)));
+        assert!(!filter.matches(&entry("/a.rs", "Rust", None, 1)));
+        assert!(!filter.matches(&entry("/a.lua", "Lua", Some("timeit"), 1)));
+        assert!(Filter::default().matches(&entry("/a.lua", "Lua", None, 1)));
+    }
+
+    #[test]
+    fn csv_quotes_fields_with_separators() {
+        let export = export(
+            Filter::default(),
+            vec![entry("/a, \"b\".rs", "Rust", Some("app"), 5)],
+        );
+        let csv = written(&export, Format::Csv, false);
+        let row = csv.lines().nth(1).unwrap();
+        assert!(row.starts_with("\"/a, \"\"b\"\".rs\",Rust,app,"));
+        assert!(row.ends_with(",5"));
+    }
+
+    #[test]
+    fn json_has_version_entries_and_days() {
+        let export = export(
+            Filter::default(),
+            vec![
+                entry("/a.rs", "Rust", Some("app"), 60),
+                entry("/b.lua", "Lua", None, 30),
+            ],
+        );
+        let json = written(&export, Format::Json, false);
+        let document: ExportDocument = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(document.version, EXPORT_VERSION);
+        assert_eq!(document.entries.len(), 2);
+        assert_eq!(document.days.len(), 1);
+        assert_eq!(document.days[0].total_secs, 90);
+        assert_eq!(document.days[0].languages["Rust"], 60);
+        assert_eq!(document.days[0].projects.len(), 1);
+    }
+
+    #[test]
+    fn markdown_escapes_pipes() {
+        let export = export(
+            Filter::default(),
+            vec![entry("/a|b.rs", "Rust", Some("app"), 5)],
+        );
+        let markdown = written(&export, Format::Markdown, false);
+        assert!(markdown.contains("/a\\|b.rs"));
+
+        let daily = written(&export, Format::Markdown, true);
+        assert!(daily.contains("| Rust 5s | app 5s |"));
+    }
+}
@@ -1,80 +1,263 @@
-use std::{path::PathBuf, time::SystemTime};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
 
-use tokio::time::{Duration, Instant};
+/// a stretch of time during which a file had attention
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interval {
+    pub start: SystemTime,
+    pub end: SystemTime,
+}
+
+impl Interval {
+    /// an interval ending before it started (the wall clock was set back)
+    /// is cut down to nothing instead of going negative
+    pub fn new(start: SystemTime, end: SystemTime) -> Self {
+        Self {
+            start,
+            end: end.max(start),
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.end
+            .duration_since(self.start)
+            .unwrap_or(Duration::ZERO)
+    }
+}
 
+/// the time spent on a single file, kept as the list of intervals during
+/// which it was active. the methods take the current time so that sessions
+/// can be driven by any clock
 #[derive(Debug, Clone)]
 pub struct FileSession {
     pub path: PathBuf,
     pub language: String,
     pub project: Option<String>,
-    pub total_duration: Duration,
-    pub current_session_start: Option<Instant>,
-    pub is_active: bool,
-    pub last_activity: SystemTime,
+    // finished intervals, oldest first
+    intervals: Vec<Interval>,
+    // start of the running interval, None while paused
+    active_since: Option<SystemTime>,
 }
 
 impl FileSession {
-    pub fn new(path: PathBuf, language: String) -> Self {
+    /// creates a session that is active from now on
+    pub fn new(path: PathBuf, language: String, now: SystemTime) -> Self {
         Self {
             language,
             project: detect_project(&path),
             path,
-            total_duration: Duration::ZERO,
-            current_session_start: Some(Instant::now()),
-            is_active: true,
-            last_activity: SystemTime::now(),
+            intervals: Vec::new(),
+            active_since: Some(now),
         }
     }
 
-    pub fn pause(&mut self) {
-        if self.is_active {
-            self.is_active = false;
-            let start = self.last_activity.elapsed().unwrap_or(Duration::ZERO);
-            self.total_duration += start;
+    /// creates a session that doesn't run until it's resumed
+    pub fn new_paused(path: PathBuf, language: String) -> Self {
+        Self {
+            language,
+            project: detect_project(&path),
+            path,
+            intervals: Vec::new(),
+            active_since: None,
         }
     }
 
-    pub fn resume(&mut self) {
-        if !self.is_active {
-            self.current_session_start = Some(Instant::now());
-            self.is_active = true;
-            self.last_activity = SystemTime::now();
+    pub fn is_active(&self) -> bool {
+        self.active_since.is_some()
+    }
+
+    /// ends the running interval at now and returns it.
+    /// returns None if the session was already paused
+    pub fn pause(&mut self, now: SystemTime) -> Option<Interval> {
+        let interval = Interval::new(self.active_since.take()?, now);
+        self.intervals.push(interval);
+        Some(interval)
+    }
+
+    /// starts a new interval at now, unless one is running already
+    pub fn resume(&mut self, now: SystemTime) {
+        if self.active_since.is_none() {
+            self.active_since = Some(now);
         }
     }
 
-    pub fn get_current_duration(&self) -> Duration {
-        let start_time = if let Some(start) = self.current_session_start {
-            start.elapsed()
-        } else {
-            Duration::ZERO
-        };
+    /// the finished intervals, oldest first
+    pub fn intervals(&self) -> &[Interval] {
+        &self.intervals
+    }
+
+    /// the running interval up to now, if the session is active
+    pub fn current_interval(&self, now: SystemTime) -> Option<Interval> {
+        self.active_since.map(|start| Interval::new(start, now))
+    }
 
-        self.total_duration + start_time
+    /// total time of every interval, including the running one up to now
+    pub fn total_duration(&self, now: SystemTime) -> Duration {
+        self.intervals
+            .iter()
+            .chain(self.current_interval(now).as_ref())
+            .map(Interval::duration)
+            .sum()
     }
 }
 
-pub fn detect_project(path: &PathBuf) -> Option<String> {
-    let mut current_dir = path.as_path();
-
-    loop {
-        if let Some(parent) = current_dir.parent() {
-            if parent.join(".git").exists()
-                || parent.join("Cargo.toml").exists()
-                || parent.join("package.json").exists()
-                || parent.join("pyproject.toml").exists()
-                || parent.join("requirements.txt").exists()
-                || parent.join("go.mod").exists()
-                || parent.join("pom.xml").exists()
-            {
-                return parent
-                    .file_name()
-                    .and_then(|s| s.to_str())
-                    .map(|name| name.to_string());
-            }
-            current_dir = parent;
-        } else {
-            break;
+pub fn detect_project(path: &Path) -> Option<String> {
+    let mut current_dir = path;
+
+    while let Some(parent) = current_dir.parent() {
+        if parent.join(".git").exists()
+            || parent.join("Cargo.toml").exists()
+            || parent.join("package.json").exists()
+            || parent.join("pyproject.toml").exists()
+            || parent.join("requirements.txt").exists()
+            || parent.join("go.mod").exists()
+            || parent.join("pom.xml").exists()
+        {
+            return parent
+                .file_name()
+                .and_then(|s| s.to_str())
+                .map(|name| name.to_string());
         }
+        current_dir = parent;
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a point in time, secs seconds after an arbitrary start
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000 + secs)
+    }
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    fn session(now: SystemTime) -> FileSession {
+        FileSession::new(PathBuf::from("/work/main.rs"), "Rust".to_string(), now)
+    }
+
+    #[test]
+    fn new_session_is_running() {
+        let session = session(at(0));
+
+        assert!(session.is_active());
+        assert!(session.intervals().is_empty());
+        assert_eq!(session.total_duration(at(0)), Duration::ZERO);
+        assert_eq!(session.total_duration(at(7)), secs(7));
+    }
+
+    #[test]
+    fn pause_closes_the_running_interval() {
+        let mut session = session(at(0));
+
+        let interval = session.pause(at(30));
+
+        assert_eq!(interval, Some(Interval::new(at(0), at(30))));
+        assert!(!session.is_active());
+        assert_eq!(session.intervals(), [Interval::new(at(0), at(30))]);
+        assert_eq!(session.total_duration(at(30)), secs(30));
+    }
+
+    #[test]
+    fn paused_time_is_not_counted() {
+        let mut session = session(at(0));
+        session.pause(at(10));
+
+        assert_eq!(session.total_duration(at(10)), secs(10));
+        assert_eq!(session.total_duration(at(500)), secs(10));
+        assert_eq!(session.current_interval(at(500)), None);
+    }
+
+    #[test]
+    fn pausing_twice_counts_once() {
+        let mut session = session(at(0));
+        session.pause(at(10));
+
+        assert_eq!(session.pause(at(20)), None);
+        assert_eq!(session.intervals().len(), 1);
+        assert_eq!(session.total_duration(at(20)), secs(10));
+    }
+
+    #[test]
+    fn resuming_a_running_session_keeps_its_start() {
+        let mut session = session(at(0));
+        session.resume(at(15));
+
+        assert_eq!(
+            session.current_interval(at(20)),
+            Some(Interval::new(at(0), at(20)))
+        );
+        assert_eq!(session.pause(at(20)), Some(Interval::new(at(0), at(20))));
+    }
+
+    #[test]
+    fn every_pause_resume_cycle_is_its_own_interval() {
+        let mut session = session(at(0));
+        session.pause(at(10));
+        session.resume(at(100));
+        session.pause(at(130));
+        session.resume(at(200));
+        session.pause(at(201));
+
+        assert_eq!(
+            session.intervals(),
+            [
+                Interval::new(at(0), at(10)),
+                Interval::new(at(100), at(130)),
+                Interval::new(at(200), at(201)),
+            ]
+        );
+        assert_eq!(session.total_duration(at(1000)), secs(41));
+    }
+
+    #[test]
+    fn total_includes_the_running_interval() {
+        let mut session = session(at(0));
+        session.pause(at(10));
+        session.resume(at(50));
+
+        assert_eq!(session.total_duration(at(50)), secs(10));
+        assert_eq!(session.total_duration(at(65)), secs(25));
+        // nothing is recorded until the interval ends
+        assert_eq!(session.intervals().len(), 1);
+    }
+
+    #[test]
+    fn pause_and_resume_at_the_same_time() {
+        let mut session = session(at(0));
+        session.pause(at(10));
+        session.resume(at(10));
+        session.pause(at(20));
+
+        assert_eq!(session.intervals().len(), 2);
+        assert_eq!(session.total_duration(at(20)), secs(20));
+    }
+
+    #[test]
+    fn empty_interval_when_paused_right_away() {
+        let mut session = session(at(5));
+
+        let interval = session.pause(at(5)).unwrap();
+
+        assert_eq!(interval.duration(), Duration::ZERO);
+        assert_eq!(session.total_duration(at(60)), Duration::ZERO);
+    }
+
+    #[test]
+    fn clock_going_backwards_does_not_go_negative() {
+        let mut session = session(at(100));
+
+        let interval = session.pause(at(40)).unwrap();
+
+        assert_eq!(interval, Interval::new(at(100), at(100)));
+        assert_eq!(interval.duration(), Duration::ZERO);
+        assert_eq!(session.total_duration(at(40)), Duration::ZERO);
+    }
+}
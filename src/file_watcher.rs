@@ -1,226 +1,350 @@
 use crossbeam::channel::Receiver;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::HashMap,
     path::{Path, PathBuf},
     sync::Arc,
     time::{Duration, Instant},
 };
 
-use notify::{Event, event::AccessMode};
+use notify::{
+    Event,
+    event::{AccessKind, CreateKind, DataChange, ModifyKind, RemoveKind, RenameMode},
+};
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{error, info};
+
+use crate::{
+    clock::{SharedClock, SystemClock},
+    config::WatcherConfig,
+    ignore_rules::IgnoreRules,
+    language::LanguageRegistry,
+    language_detector::LanguageDetector,
+    temp_files,
+    tracking_event::TrackingEvents,
+};
+
+/// what happened to a tracked file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileChange {
+    Modified(PathBuf),
+    Opened(PathBuf),
+    Closed(PathBuf),
+    Renamed { from: PathBuf, to: PathBuf },
+}
 
 /// file watcher is used to keep track of file events
 /// it will keep a map of last events and the time they happened
-/// it will also keep track of the file extension in which the
+/// it will also keep track of the language of the files in which the
 /// modifications occur and will ignore the files for which ignore
-/// patterns apply
+/// rules apply. each watched root has its own file watcher
 pub struct FileWatcher {
     //TODO: use atomics? with rwlock?
     last_events: HashMap<PathBuf, Instant>,
     debouncing_duration: Duration,
-    source_extensions: HashSet<&'static str>,
-    ignore_patterns: Vec<&'static str>,
+    ignore_rules: IgnoreRules,
+    languages: LanguageDetector,
+    clock: SharedClock,
 }
 
 impl FileWatcher {
-    /// creates a new file watcher. doesnt take anything as input for now
-    pub fn new() -> Self {
-        let source_extensions = HashSet::from([
-            ".rs", ".py", ".js", ".jsx", ".ts", ".tsx", ".go", ".cpp", ".h", ".c", ".lua",
-        ]);
-
-        let ignore_patterns = vec![
-            // Build artifacts
-            "/target/",
-            "/build/",
-            "/dist/",
-            "/out/",
-            "/.next/",
-            // Dependencies
-            "/node_modules/",
-            "/vendor/",
-            "/.venv/",
-            "/venv/",
-            "/env/",
-            // Version control
-            "/.git/",
-            "/.svn/",
-            "/.hg/",
-            // IDE/Editor files
-            "/.vscode/",
-            "/.idea/",
-            "/.vs/",
-            // Temporary files
-            "/tmp/",
-            "/temp/",
-            "/.tmp/",
-            // Cache directories
-            "/.cache/",
-            "/cache/",
-            "/__pycache__/",
-            "/.pytest_cache/",
-            // Log files
-            "/logs/",
-            "/.log/",
-        ];
-
-        Self {
+    /// creates a new file watcher for the given root using the given watcher
+    /// settings. only files of the languages tracked by the registry are reported
+    pub fn new(
+        root: &Path,
+        config: &WatcherConfig,
+        languages: LanguageRegistry,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
             last_events: HashMap::new(),
-            debouncing_duration: Duration::from_millis(100),
-            source_extensions,
-            ignore_patterns,
-        }
+            debouncing_duration: config.debounce(),
+            ignore_rules: IgnoreRules::new(root, &config.ignore_patterns)?,
+            languages: LanguageDetector::new(languages),
+            clock: SystemClock::shared(),
+        })
     }
 
-    /// checks to see if the event received is actually a modification
-    /// type of event. if yes, then returns the path of the modified file
-    pub fn process_event(&mut self, event: &Event) -> Option<PathBuf> {
+    /// makes the debouncer take the time from the given clock instead of the system's
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// checks to see if the event received is a modification, an open, a
+    /// close or a rename of a tracked file. if yes, then returns what happened
+    /// to which file. changes to the swap, backup and temporary files of
+    /// editors are reported as changes to the file they belong to
+    pub fn process_event(&mut self, event: &Event) -> Option<FileChange> {
+        // changed ignore files are picked up whatever the kind of the event is
+        for path in &event.paths {
+            self.ignore_rules.handle_change(path);
+        }
+        // new content can bring a shebang or a modeline along
+        if let notify::EventKind::Remove(_)
+        | notify::EventKind::Modify(ModifyKind::Name(_) | ModifyKind::Data(_)) = event.kind
+        {
+            for path in &event.paths {
+                self.languages.forget(path);
+            }
+        }
+
         match event.kind {
-            notify::EventKind::Modify(notify::event::ModifyKind::Data(
-                notify::event::DataChange::Any,
-            )) => {
-                // will check for keyboard movement later
-                for path in &event.paths {
-                    if self.should_ignore(path) || self.should_debounce(path) {
-                        continue;
-                    } else {
-                        return Some(path.clone());
-                    }
-                }
-                None
+            // files written by editors that save by replacing the file show up as created
+            notify::EventKind::Modify(ModifyKind::Data(DataChange::Any))
+            | notify::EventKind::Create(CreateKind::File) => {
+                self.first_tracked(event, true).map(FileChange::Modified)
+            }
+            // inotify reports every open, by editors as well as by compilers
+            // and search tools. the manager only keeps a session ready for
+            // them, the active file stays as it is. opens aren't debounced,
+            // an in-place save is an open followed by the modification
+            notify::EventKind::Access(AccessKind::Open(_)) => {
+                self.first_tracked(event, false).map(FileChange::Opened)
             }
-            notify::EventKind::Access(notify::event::AccessKind::Open(AccessMode::Any)) => {
-                todo!(
-                    "should start the time for that file, inside this time, each input activity should be recorded"
-                );
+            // closes aren't debounced, they usually follow the open or
+            // modification of the same file within a few milliseconds.
+            // removed files are closed for good
+            notify::EventKind::Access(AccessKind::Close(_))
+            | notify::EventKind::Remove(RemoveKind::File) => {
+                self.first_tracked(event, false).map(FileChange::Closed)
             }
-            notify::EventKind::Access(notify::event::AccessKind::Close(AccessMode::Any)) => {
-                todo!(
-                    "should stop the time for this file and return the amount of time that this file has been active"
-                );
+            // a rename comes as a From and a To event followed by a Both event
+            // with both paths. only the last one is needed
+            notify::EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                let [from, to] = event.paths.as_slice() else {
+                    return None;
+                };
+                self.process_rename(from, to)
             }
             _ => None,
         }
     }
 
+    /// works out what a rename means for the tracked files
+    fn process_rename(&mut self, from: &Path, to: &Path) -> Option<FileChange> {
+        // the first step of saving in vim, the file is moved to its backup
+        // and a new one is written in its place
+        if temp_files::is_temp_file(to) {
+            return None;
+        }
+        // saving by writing to a temporary file and moving it over the
+        // original, or a file that was moved in from an untracked name
+        if temp_files::is_temp_file(from) || self.should_ignore(from) {
+            return (!self.should_ignore(to)).then(|| FileChange::Modified(to.to_path_buf()));
+        }
+        if self.should_ignore(to) {
+            return Some(FileChange::Closed(from.to_path_buf()));
+        }
+        Some(FileChange::Renamed {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        })
+    }
+
+    /// returns the first path of the event that isn't ignored (or debounced).
+    /// swap, backup and temporary files stand for the file they belong to,
+    /// except when they are opened, closed or removed
+    fn first_tracked(&mut self, event: &Event, follow_temp: bool) -> Option<PathBuf> {
+        for path in &event.paths {
+            let path = if !temp_files::is_temp_file(path) {
+                path.clone()
+            } else if follow_temp && let Some(original) = temp_files::original_path(path) {
+                original
+            } else {
+                continue;
+            };
+
+            if self.should_ignore(&path) || (follow_temp && self.should_debounce(&path)) {
+                continue;
+            } else {
+                return Some(path);
+            }
+        }
+        None
+    }
+
     /// checks and sees if the diff in time between the new event
     /// and last event is less than debouncing duration.
     /// also saves the new event instant if time diff is
     /// more than debouncing duration
     fn should_debounce(&mut self, path: &Path) -> bool {
-        let now = Instant::now();
+        let now = self.clock.instant();
         let path = path.to_path_buf();
-        if let Some(last_event) = self.last_events.get(&path) {
-            if now.duration_since(*last_event) < self.debouncing_duration {
-                return true;
-            }
+        if let Some(last_event) = self.last_events.get(&path)
+            && now.duration_since(*last_event) < self.debouncing_duration
+        {
+            return true;
         }
         self.last_events.insert(path, now);
         false
     }
 
-    // checks and sees if the path contains modifications
-    // in files that was mentioned in ignored paths.
-    // this should later be used to check .ignore files(?)
-    fn should_ignore(&self, path: &Path) -> bool {
-        let path_str = path.to_string_lossy();
-        for pattern in &self.ignore_patterns {
-            if path_str.contains(pattern) {
-                return true;
-            }
-        }
-
-        // checking file extensions
-        if let Some(extension) = path.extension() {
-            if let Some(ext_str) = extension.to_str() {
-                let ext_with_dot = format!(".{ext_str}");
-                if self.source_extensions.contains(ext_with_dot.as_str()) {
-                    return false;
-                }
-            }
+    // checks and sees if the path is covered by the ignore rules
+    // of the root (.gitignore, .ignore and .timeitignore files and
+    // the ignore patterns of the config) or isn't in a tracked language
+    fn should_ignore(&mut self, path: &Path) -> bool {
+        if self.ignore_rules.is_ignored(path, path.is_dir()) {
+            return true;
         }
-        true
+        let language = self.languages.detect(path);
+        !self.languages.registry().is_tracked(&language)
     }
-    pub async fn handle_file_watcher(&mut self, rx: Arc<Receiver<crate::Result<Event>>>) {
+
+    /// receives raw notify events, filters them and sends a FileModified,
+    /// FileOpened, FileClosed or FileRenamed tracking event through the tracking channel
+    /// for each change to a source file. it blocks until the notify watcher
+    /// is dropped, so it must run on a thread of its own
+    pub fn handle_file_watcher(
+        &mut self,
+        rx: Arc<Receiver<crate::Result<Event>>>,
+        tracking_sender: UnboundedSender<TrackingEvents>,
+    ) {
         for res in rx.as_ref() {
             match res {
                 Ok(event) => {
-                    if let Some(path) = self.process_event(&event) {
-                        let language = detect_language(&path).unwrap_or("unknown");
-                        let relative_path = path.strip_prefix("./").unwrap_or(&path);
-
-                        println!("file modified: {} ({language})", relative_path.display());
+                    let Some(change) = self.process_event(&event) else {
+                        continue;
+                    };
+                    if tracking_sender.send(self.tracking_event(change)).is_err() {
+                        // manager is gone, nothing left to report to
+                        return;
                     }
                 }
                 Err(e) => {
-                    println!("file watcher error: {e}");
+                    error!("file watcher error: {e}");
                 }
             }
         }
     }
-}
 
-// TODO: use an enum
-pub fn detect_language(path: &Path) -> Option<&'static str> {
-    if let Some(extension) = path.extension() {
-        if let Some(ext_str) = extension.to_str() {
-            match ext_str {
-                "rs" => Some("Rust"),
-                "py" => Some("Python"),
-                "js" => Some("JavaScript"),
-                "ts" => Some("TypeScript"),
-                "jsx" => Some("JavaScript React"),
-                "tsx" => Some("TypeScript React"),
-                "go" => Some("Go"),
-                "cpp" | "cc" | "cxx" => Some("C++"),
-                "c" => Some("C"),
-                "h" | "hpp" => Some("C/C++ Header"),
-                "java" => Some("Java"),
-                "kt" => Some("Kotlin"),
-                "php" => Some("PHP"),
-                "rb" => Some("Ruby"),
-                "swift" => Some("Swift"),
-                "scala" => Some("Scala"),
-                "clj" => Some("Clojure"),
-                "hs" => Some("Haskell"),
-                "elm" => Some("Elm"),
-                "dart" => Some("Dart"),
-                "lua" => Some("Lua"),
-                "vim" => Some("Vim Script"),
-                "sh" | "bash" => Some("Bash"),
-                "zsh" => Some("Zsh"),
-                "fish" => Some("Fish"),
-                "ps1" => Some("PowerShell"),
-                "sql" => Some("SQL"),
-                "html" => Some("HTML"),
-                "css" => Some("CSS"),
-                "scss" => Some("SCSS"),
-                "sass" => Some("Sass"),
-                "less" => Some("Less"),
-                "md" => Some("Markdown"),
-                "yml" | "yaml" => Some("YAML"),
-                "toml" => Some("TOML"),
-                "json" => Some("JSON"),
-                "xml" => Some("XML"),
-                _ => Some("Unknown"),
-            }
-        } else {
-            None
-        }
-    } else {
-        // handling files without extensions
-        if let Some(filename) = path.file_name() {
-            if let Some(name_str) = filename.to_str() {
-                match name_str {
-                    "Dockerfile" => Some("Docker"),
-                    "Makefile" => Some("Makefile"),
-                    "CMakeLists.txt" => Some("CMake"),
-                    _ => Some("Text"),
-                }
-            } else {
-                None
-            }
-        } else {
-            None
+    /// turns a file change into the tracking event the manager expects
+    fn tracking_event(&mut self, change: FileChange) -> TrackingEvents {
+        let (action, path) = match &change {
+            FileChange::Modified(path) => ("modified", path),
+            FileChange::Opened(path) => ("opened", path),
+            FileChange::Closed(path) => ("closed", path),
+            FileChange::Renamed { to, .. } => ("renamed", to),
+        };
+        let language = self.languages.detect(path);
+        let relative_path = path.strip_prefix("./").unwrap_or(path);
+        info!("file {action}: {} ({language})", relative_path.display());
+
+        let language = language.name().to_string();
+        match change {
+            FileChange::Modified(path) => TrackingEvents::FileModified {
+                path: path.to_string_lossy().to_string(),
+                language: Some(language),
+                project: None,
+            },
+            FileChange::Opened(path) => TrackingEvents::FileOpened {
+                path: path.to_string_lossy().to_string(),
+                language,
+            },
+            FileChange::Closed(path) => TrackingEvents::FileClosed {
+                path: path.to_string_lossy().to_string(),
+            },
+            FileChange::Renamed { from, to } => TrackingEvents::FileRenamed {
+                from: from.to_string_lossy().to_string(),
+                to: to.to_string_lossy().to_string(),
+                language,
+            },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use notify::event::AccessMode;
+
+    #[test]
+    fn events_within_the_debounce_duration_are_dropped() {
+        let root = std::env::temp_dir().join(format!("timeit-debounce-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let clock = MockClock::new();
+        let mut watcher = FileWatcher::new(
+            &root,
+            &WatcherConfig::default(),
+            LanguageRegistry::default(),
+        )
+        .unwrap()
+        .with_clock(clock.shared());
+        let path = root.join("main.rs");
+
+        assert!(!watcher.should_debounce(&path));
+        clock.advance(Duration::from_millis(99));
+        assert!(watcher.should_debounce(&path));
+        // measured from the last event that got through
+        clock.advance(Duration::from_millis(1));
+        assert!(!watcher.should_debounce(&path));
+        // other files aren't affected
+        assert!(!watcher.should_debounce(&root.join("lib.rs")));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn saving_in_place_is_a_modification() {
+        let root = std::env::temp_dir().join(format!("timeit-in-place-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let mut watcher = FileWatcher::new(
+            &root,
+            &WatcherConfig::default(),
+            LanguageRegistry::default(),
+        )
+        .unwrap()
+        .with_clock(MockClock::new().shared());
+        let path = root.join("main.rs");
+        std::fs::write(&path, "fn main() {}\n").unwrap();
+
+        // what inotify reports for a write to an existing file, all within
+        // the same millisecond
+        let events = [
+            notify::EventKind::Access(AccessKind::Open(AccessMode::Write)),
+            notify::EventKind::Modify(ModifyKind::Data(DataChange::Any)),
+            notify::EventKind::Access(AccessKind::Close(AccessMode::Write)),
+        ];
+        let changes: Vec<_> = events
+            .into_iter()
+            .map(|kind| watcher.process_event(&Event::new(kind).add_path(path.clone())))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                Some(FileChange::Opened(path.clone())),
+                Some(FileChange::Modified(path.clone())),
+                Some(FileChange::Closed(path)),
+            ]
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn new_content_is_detected_again() {
+        let root = std::env::temp_dir().join(format!("timeit-redetect-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let mut watcher = FileWatcher::new(
+            &root,
+            &WatcherConfig::default(),
+            LanguageRegistry::default(),
+        )
+        .unwrap()
+        .with_clock(MockClock::new().shared());
+        let path = root.join("deploy");
+        let modified = Event::new(notify::EventKind::Modify(ModifyKind::Data(DataChange::Any)))
+            .add_path(path.clone());
+
+        // plain text isn't tracked
+        std::fs::write(&path, "todo\n").unwrap();
+        assert_eq!(watcher.process_event(&modified), None);
+
+        std::fs::write(&path, "#!/usr/bin/env python3\nprint('hi')\n").unwrap();
+        assert_eq!(
+            watcher.process_event(&modified),
+            Some(FileChange::Modified(path))
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
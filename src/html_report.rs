@@ -0,0 +1,456 @@
+use chrono::{Datelike, NaiveDate};
+use std::{
+    collections::HashMap,
+    f64::consts::PI,
+    fmt::{self, Write},
+    hash::Hash,
+    time::Duration,
+};
+
+use crate::{report::Report, stats::format_duration};
+
+/// colors of projects and languages, in order of their time. anything past
+/// the end of the list is grouped as "other"
+const PALETTE: &[&str] = &[
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#ff9da7",
+];
+const OTHER_COLOR: &str = "#bab0ac";
+/// heatmap colors from nothing tracked to the busiest days
+const HEAT: [&str; 5] = ["#ebedf0", "#c6e48b", "#7bc96f", "#239a3b", "#196127"];
+
+const CHART_WIDTH: f64 = 720.0;
+const CHART_HEIGHT: f64 = 240.0;
+/// room for the axis labels left of and below the bars
+const AXIS_WIDTH: f64 = 60.0;
+const AXIS_HEIGHT: f64 = 24.0;
+const CELL: f64 = 14.0;
+const CELL_GAP: f64 = 3.0;
+
+const STYLE: &str = "body{font-family:system-ui,sans-serif;margin:2rem auto;max-width:800px;color:#24292e}
+h1{font-size:1.6rem;margin-bottom:.2rem}
+h2{font-size:1.1rem;margin-top:2rem;border-bottom:1px solid #e1e4e8;padding-bottom:.3rem}
+.summary{color:#586069}
+.legend{display:flex;flex-wrap:wrap;gap:.4rem 1rem;font-size:.85rem;margin:.5rem 0}
+.legend span::before{content:'';display:inline-block;width:.8rem;height:.8rem;margin-right:.3rem;background:var(--c);vertical-align:-1px}
+svg text{font-size:11px;fill:#586069}
+table{border-collapse:collapse;width:100%;font-size:.9rem}
+td,th{padding:.3rem .5rem;border-bottom:1px solid #eaecef;text-align:left}
+td.num,th.num{text-align:right;white-space:nowrap}
+td.path{word-break:break-all}";
+
+/// renders the report as a single html page with inline svg charts. it
+/// doesn't load anything, so the file can be opened from anywhere
+pub fn render(report: &Report, top: usize) -> String {
+    let mut out = String::new();
+    write_page(&mut out, report, top.max(1)).expect("writing to a string can't fail");
+    out
+}
+
+fn write_page(out: &mut String, report: &Report, top: usize) -> fmt::Result {
+    let title = report.period.title();
+    let (from, to) = report.period.range();
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>time report: {}</title>", escape(&title))?;
+    writeln!(out, "<style>\n{STYLE}\n</style>\n</head>\n<body>")?;
+    writeln!(out, "<h1>{}</h1>", escape(&title))?;
+
+    if report.days.is_empty() {
+        writeln!(
+            out,
+            "<p class=\"summary\">nothing tracked between {from} and {to}</p>"
+        )?;
+        return writeln!(out, "</body>\n</html>");
+    }
+    write!(
+        out,
+        "<p class=\"summary\">total {}",
+        format_duration(report.total.total_time)
+    )?;
+    if from != to {
+        write!(
+            out,
+            ", {} per day",
+            format_duration(report.average_per_day())
+        )?;
+        if let Some(day) = report.busiest_day() {
+            write!(
+                out,
+                ", busiest day {} with {}",
+                day.date,
+                format_duration(day.total_time)
+            )?;
+        }
+    }
+    writeln!(out, "</p>")?;
+
+    // the same colors in every chart
+    let projects = top_names(&report.total.entries_by_project, top);
+    let languages = top_names(&report.total.entries_by_lang, top);
+
+    writeln!(out, "<h2>projects per day</h2>")?;
+    write_legend(out, &projects)?;
+    write_day_bars(out, report, &projects)?;
+
+    writeln!(out, "<h2>languages</h2>")?;
+    write_legend(out, &languages)?;
+    write_pie(out, &report.total.entries_by_lang, &languages)?;
+
+    writeln!(out, "<h2>activity</h2>")?;
+    write_heatmap(out, report)?;
+
+    writeln!(out, "<h2>top files</h2>")?;
+    write_files(out, report, top)?;
+    writeln!(out, "</body>\n</html>")
+}
+
+/// the longest names first, cut off after top
+fn top_names<K: Eq + Hash + AsRef<str>>(
+    durations: &HashMap<K, Duration>,
+    top: usize,
+) -> Vec<String> {
+    let mut durations: Vec<_> = durations.iter().collect();
+    durations.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.as_ref().cmp(b.0.as_ref())));
+    durations
+        .into_iter()
+        .take(top.min(PALETTE.len()))
+        .map(|(name, _)| name.as_ref().to_string())
+        .collect()
+}
+
+fn color(names: &[String], name: &str) -> &'static str {
+    names
+        .iter()
+        .position(|known| known == name)
+        .map(|index| PALETTE[index])
+        .unwrap_or(OTHER_COLOR)
+}
+
+fn write_legend(out: &mut String, names: &[String]) -> fmt::Result {
+    write!(out, "<div class=\"legend\">")?;
+    for name in names {
+        write!(
+            out,
+            "<span style=\"--c:{}\">{}</span>",
+            color(names, name),
+            escape(name)
+        )?;
+    }
+    writeln!(out, "<span style=\"--c:{OTHER_COLOR}\">other</span></div>")
+}
+
+/// a bar per day of the period, stacked by project. time of other projects
+/// and of files outside any project is stacked on top as "other"
+fn write_day_bars(out: &mut String, report: &Report, projects: &[String]) -> fmt::Result {
+    let (from, to) = report.period.range();
+    let dates: Vec<NaiveDate> = from.iter_days().take_while(|date| *date <= to).collect();
+    let days: HashMap<String, _> = report
+        .days
+        .iter()
+        .map(|day| (day.date.clone(), day))
+        .collect();
+    let max = report
+        .days
+        .iter()
+        .map(|day| day.total_time)
+        .max()
+        .unwrap_or_default()
+        .as_secs_f64()
+        .max(1.0);
+
+    let plot_width = CHART_WIDTH - AXIS_WIDTH;
+    let plot_height = CHART_HEIGHT - AXIS_HEIGHT;
+    let slot = plot_width / dates.len() as f64;
+    let bar_width = (slot * 0.8).max(1.0);
+    // a label every few days, so that they don't overlap
+    let label_every = (42.0 / slot).ceil().max(1.0) as usize;
+
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{CHART_WIDTH}\" height=\"{CHART_HEIGHT}\" viewBox=\"0 0 {CHART_WIDTH} {CHART_HEIGHT}\">"
+    )?;
+    for step in 0..=4 {
+        let share = step as f64 / 4.0;
+        let y = plot_height * (1.0 - share);
+        writeln!(
+            out,
+            "<line x1=\"{AXIS_WIDTH}\" y1=\"{y:.1}\" x2=\"{CHART_WIDTH}\" y2=\"{y:.1}\" stroke=\"#eaecef\"/>"
+        )?;
+        writeln!(
+            out,
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>",
+            AXIS_WIDTH - 6.0,
+            y + 4.0,
+            format_duration(Duration::from_secs_f64(max * share))
+        )?;
+    }
+
+    for (index, date) in dates.iter().enumerate() {
+        let x = AXIS_WIDTH + slot * index as f64 + (slot - bar_width) / 2.0;
+        if index % label_every == 0 {
+            writeln!(
+                out,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>",
+                x + bar_width / 2.0,
+                CHART_HEIGHT - 6.0,
+                date.format("%b %-d")
+            )?;
+        }
+        let Some(day) = days.get(&date.to_string()) else {
+            continue;
+        };
+
+        let mut segments: Vec<(&str, Duration)> = projects
+            .iter()
+            .filter_map(|project| {
+                let duration = *day.entries_by_project.get(project)?;
+                Some((project.as_str(), duration))
+            })
+            .collect();
+        let known: Duration = segments.iter().map(|(_, duration)| *duration).sum();
+        segments.push(("other", day.total_time.saturating_sub(known)));
+
+        let mut y = plot_height;
+        for (name, duration) in segments {
+            if duration.is_zero() {
+                continue;
+            }
+            let height = plot_height * duration.as_secs_f64() / max;
+            y -= height;
+            writeln!(
+                out,
+                "<rect x=\"{x:.1}\" y=\"{y:.1}\" width=\"{bar_width:.1}\" height=\"{height:.1}\" fill=\"{}\"><title>{date} {}: {}</title></rect>",
+                color(projects, name),
+                escape(name),
+                format_duration(duration)
+            )?;
+        }
+    }
+    writeln!(out, "</svg>")
+}
+
+/// the languages of the whole period, the ones past the legend as "other"
+fn write_pie(
+    out: &mut String,
+    durations: &HashMap<String, Duration>,
+    languages: &[String],
+) -> fmt::Result {
+    const RADIUS: f64 = 100.0;
+    const CENTER: f64 = 110.0;
+
+    let total: Duration = durations.values().sum();
+    let mut slices: Vec<(&str, Duration)> = languages
+        .iter()
+        .map(|language| (language.as_str(), durations[language]))
+        .collect();
+    let known: Duration = slices.iter().map(|(_, duration)| *duration).sum();
+    slices.push(("other", total.saturating_sub(known)));
+    slices.retain(|(_, duration)| !duration.is_zero());
+
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"220\" height=\"220\" viewBox=\"0 0 220 220\">"
+    )?;
+    // starting at the top, clockwise
+    let mut angle = -PI / 2.0;
+    for (name, duration) in slices {
+        let share = duration.as_secs_f64() / total.as_secs_f64();
+        let title = format!(
+            "{}: {} ({:.0}%)",
+            escape(name),
+            format_duration(duration),
+            share * 100.0
+        );
+        let fill = color(languages, name);
+        if share >= 0.9999 {
+            // an arc can't end where it starts
+            writeln!(
+                out,
+                "<circle cx=\"{CENTER}\" cy=\"{CENTER}\" r=\"{RADIUS}\" fill=\"{fill}\"><title>{title}</title></circle>"
+            )?;
+            continue;
+        }
+        let end = angle + share * 2.0 * PI;
+        let large = if share > 0.5 { 1 } else { 0 };
+        writeln!(
+            out,
+            "<path d=\"M{CENTER},{CENTER} L{:.2},{:.2} A{RADIUS},{RADIUS} 0 {large} 1 {:.2},{:.2} Z\" fill=\"{fill}\" stroke=\"#fff\"><title>{title}</title></path>",
+            CENTER + RADIUS * angle.cos(),
+            CENTER + RADIUS * angle.sin(),
+            CENTER + RADIUS * end.cos(),
+            CENTER + RADIUS * end.sin(),
+        )?;
+        angle = end;
+    }
+    writeln!(out, "</svg>")
+}
+
+/// a column per week and a row per weekday, darker on busier days. days
+/// outside the period are left out
+fn write_heatmap(out: &mut String, report: &Report) -> fmt::Result {
+    let (from, to) = report.period.range();
+    let first = from.week(chrono::Weekday::Mon).first_day();
+    let weeks = (to - first).num_days() as usize / 7 + 1;
+    let days: HashMap<String, Duration> = report
+        .days
+        .iter()
+        .map(|day| (day.date.clone(), day.total_time))
+        .collect();
+    let max = days.values().max().copied().unwrap_or_default();
+
+    let left = 32.0;
+    let top = 18.0;
+    let width = left + weeks as f64 * (CELL + CELL_GAP);
+    let height = top + 7.0 * (CELL + CELL_GAP);
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\">"
+    )?;
+    for (row, name) in [(0, "Mon"), (2, "Wed"), (4, "Fri")] {
+        writeln!(
+            out,
+            "<text x=\"0\" y=\"{:.1}\">{name}</text>",
+            top + row as f64 * (CELL + CELL_GAP) + CELL - 3.0
+        )?;
+    }
+
+    let mut labelled_month = None;
+    for (index, date) in first.iter_days().take_while(|date| *date <= to).enumerate() {
+        let (column, row) = (index / 7, index % 7);
+        let x = left + column as f64 * (CELL + CELL_GAP);
+        if row == 0 {
+            // label the first column of every month
+            let month = date.max(from).month();
+            if labelled_month != Some(month) {
+                labelled_month = Some(month);
+                writeln!(
+                    out,
+                    "<text x=\"{x:.1}\" y=\"12\">{}</text>",
+                    date.max(from).format("%b")
+                )?;
+            }
+        }
+        if date < from {
+            continue;
+        }
+        let duration = days.get(&date.to_string()).copied().unwrap_or_default();
+        writeln!(
+            out,
+            "<rect x=\"{x:.1}\" y=\"{:.1}\" width=\"{CELL}\" height=\"{CELL}\" rx=\"2\" fill=\"{}\"><title>{date}: {}</title></rect>",
+            top + row as f64 * (CELL + CELL_GAP),
+            HEAT[heat_level(duration, max)],
+            format_duration(duration)
+        )?;
+    }
+    writeln!(out, "</svg>")
+}
+
+/// 0 for nothing tracked, up to 4 for the busiest day
+fn heat_level(duration: Duration, max: Duration) -> usize {
+    if duration.is_zero() || max.is_zero() {
+        return 0;
+    }
+    let share = duration.as_secs_f64() / max.as_secs_f64();
+    ((share * 4.0).ceil() as usize).clamp(1, 4)
+}
+
+fn write_files(out: &mut String, report: &Report, top: usize) -> fmt::Result {
+    let mut files: Vec<_> = report.total.entries_by_file.iter().collect();
+    files.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    let total = report.total.total_time.as_secs_f64().max(1.0);
+
+    writeln!(
+        out,
+        "<table>\n<tr><th>file</th><th class=\"num\">time</th><th class=\"num\">share</th></tr>"
+    )?;
+    for (path, duration) in files.into_iter().take(top) {
+        writeln!(
+            out,
+            "<tr><td class=\"path\">{}</td><td class=\"num\">{}</td><td class=\"num\">{:.0}%</td></tr>",
+            escape(&path.display().to_string()),
+            format_duration(*duration),
+            duration.as_secs_f64() / total * 100.0
+        )?;
+    }
+    writeln!(out, "</table>")
+}
+
+/// makes names safe to put into html text and attributes
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        report::Period,
+        stats::{DailyStats, TimeEntry},
+    };
+    use chrono::{Local, TimeZone};
+    use std::path::PathBuf;
+
+    fn report(entries: &[(&str, &str, Option<&str>, u64)]) -> Report {
+        let date = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let start_time = Local
+            .from_local_datetime(&date.and_hms_opt(12, 0, 0).unwrap())
+            .unwrap()
+            .into();
+        let mut day = DailyStats::new(date.to_string());
+        for (path, language, project, secs) in entries {
+            day.add_entry(&TimeEntry {
+                path: PathBuf::from(path),
+                language: language.to_string(),
+                project: project.map(str::to_string),
+                duration: Duration::from_secs(*secs),
+                start_time,
+                end_time: start_time + Duration::from_secs(*secs),
+            });
+        }
+        let mut total = DailyStats::new(String::new());
+        total.merge(&day);
+        Report {
+            period: Period::Week(date),
+            days: vec![day],
+            total,
+        }
+    }
+
+    #[test]
+    fn page_is_self_contained_and_escaped() {
+        let html = render(&report(&[("/src/<a>.rs", "Rust", Some("app & co"), 60)]), 5);
+        assert!(html.contains("/src/&lt;a&gt;.rs"));
+        assert!(html.contains("app &amp; co"));
+        // nothing is loaded from elsewhere
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("src="));
+        assert!(!html.contains("href="));
+        assert_eq!(html.matches("<svg").count(), 3);
+    }
+
+    #[test]
+    fn single_language_is_a_full_circle() {
+        let html = render(&report(&[("/a.rs", "Rust", None, 60)]), 5);
+        assert!(html.contains("<circle"));
+    }
+
+    #[test]
+    fn heat_levels() {
+        let max = Duration::from_secs(100);
+        assert_eq!(heat_level(Duration::ZERO, max), 0);
+        assert_eq!(heat_level(Duration::from_secs(1), max), 1);
+        assert_eq!(heat_level(Duration::from_secs(60), max), 3);
+        assert_eq!(heat_level(max, max), 4);
+    }
+}
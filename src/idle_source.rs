@@ -0,0 +1,186 @@
+use anyhow::{Context, Result, bail};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use zbus::{Connection, proxy, proxy::CacheProperties};
+
+use crate::config::{InputBackend, InputConfig};
+
+/// the session of the calling process, as seen by logind
+#[proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1/session/auto"
+)]
+pub trait Session {
+    /// set by the desktop once it considers the session idle
+    #[zbus(property)]
+    fn idle_hint(&self) -> zbus::Result<bool>;
+
+    /// microseconds since unix epoch of the last change of the idle hint
+    #[zbus(property)]
+    fn idle_since_hint(&self) -> zbus::Result<u64>;
+}
+
+/// tells when the user was last active without watching every key press,
+/// by asking the desktop instead
+#[derive(Debug)]
+pub enum IdleSource {
+    /// the idle hint logind keeps for the session
+    Logind(SessionProxy<'static>),
+    /// a shell command that prints the idle time in milliseconds, like xprintidle
+    Command(String),
+}
+
+impl IdleSource {
+    /// opens the idle source of the backend, if it is one
+    pub async fn open(config: &InputConfig) -> Result<Option<Self>> {
+        match config.backend {
+            InputBackend::Logind => {
+                let connection = Connection::system()
+                    .await
+                    .context("failed to connect to the system bus")?;
+                Ok(Some(Self::logind(&connection).await?))
+            }
+            InputBackend::Command => match &config.idle_command {
+                Some(command) if !command.trim().is_empty() => {
+                    Ok(Some(Self::Command(command.clone())))
+                }
+                _ => bail!("input.backend = \"command\" needs an input.idle_command"),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// reads the idle hint of the session through the connection
+    pub async fn logind(connection: &Connection) -> Result<Self> {
+        // properties are read on every check, caching them would need
+        // signals that a plain peer connection doesn't have
+        let session = SessionProxy::builder(connection)
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await
+            .context("failed to reach the logind session")?;
+        Ok(Self::Logind(session))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            IdleSource::Logind(_) => "logind",
+            IdleSource::Command(_) => "idle command",
+        }
+    }
+
+    /// when the user was last active. a session that isn't idle counts as
+    /// active right now
+    pub async fn last_activity(&self, now: SystemTime) -> Result<SystemTime> {
+        match self {
+            IdleSource::Logind(session) => {
+                if !session
+                    .idle_hint()
+                    .await
+                    .context("failed to read IdleHint")?
+                {
+                    return Ok(now);
+                }
+                let since = session
+                    .idle_since_hint()
+                    .await
+                    .context("failed to read IdleSinceHint")?;
+                Ok(UNIX_EPOCH + Duration::from_micros(since))
+            }
+            IdleSource::Command(command) => {
+                let output = tokio::process::Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .output()
+                    .await
+                    .with_context(|| format!("failed to run {command:?}"))?;
+                if !output.status.success() {
+                    bail!("{command:?} failed with {}", output.status);
+                }
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let millis: u64 = stdout.trim().parse().with_context(|| {
+                    format!("{command:?} printed {stdout:?} instead of milliseconds")
+                })?;
+                Ok(now
+                    .checked_sub(Duration::from_millis(millis))
+                    .unwrap_or(UNIX_EPOCH))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use zbus::{Guid, connection::Builder, interface};
+
+    /// a logind session whose idle hint the test controls
+    #[derive(Debug, Clone, Default)]
+    struct MockSession {
+        // (idle hint, idle since in microseconds)
+        hint: Arc<Mutex<(bool, u64)>>,
+    }
+
+    #[interface(name = "org.freedesktop.login1.Session")]
+    impl MockSession {
+        #[zbus(property)]
+        fn idle_hint(&self) -> bool {
+            self.hint.lock().unwrap().0
+        }
+
+        #[zbus(property)]
+        fn idle_since_hint(&self) -> u64 {
+            self.hint.lock().unwrap().1
+        }
+    }
+
+    /// connects a logind source to a mock session over a private bus
+    async fn mock_logind() -> (IdleSource, MockSession, Connection) {
+        let (server, client) = tokio::net::UnixStream::pair().unwrap();
+        let session = MockSession::default();
+        let server = Builder::unix_stream(server)
+            .server(Guid::generate())
+            .unwrap()
+            .p2p()
+            .serve_at("/org/freedesktop/login1/session/auto", session.clone())
+            .unwrap()
+            .build();
+        let client = Builder::unix_stream(client).p2p().build();
+        let (server, client) = tokio::join!(server, client);
+        let source = IdleSource::logind(&client.unwrap()).await.unwrap();
+        (source, session, server.unwrap())
+    }
+
+    #[tokio::test]
+    async fn logind_idle_hint_gives_the_last_activity() {
+        let (source, session, _server) = mock_logind().await;
+        let now = UNIX_EPOCH + Duration::from_secs(1_000);
+
+        assert_eq!(source.last_activity(now).await.unwrap(), now);
+
+        *session.hint.lock().unwrap() = (true, 940_000_000);
+        assert_eq!(
+            source.last_activity(now).await.unwrap(),
+            UNIX_EPOCH + Duration::from_secs(940)
+        );
+
+        *session.hint.lock().unwrap() = (false, 990_000_000);
+        assert_eq!(source.last_activity(now).await.unwrap(), now);
+    }
+
+    #[tokio::test]
+    async fn command_prints_idle_milliseconds() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000);
+        let source = IdleSource::Command("echo 1500".to_string());
+        assert_eq!(
+            source.last_activity(now).await.unwrap(),
+            now - Duration::from_millis(1500)
+        );
+
+        let source = IdleSource::Command("echo soon".to_string());
+        assert!(source.last_activity(now).await.is_err());
+        let source = IdleSource::Command("exit 1".to_string());
+        assert!(source.last_activity(now).await.is_err());
+    }
+}
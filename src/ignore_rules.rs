@@ -0,0 +1,190 @@
+use anyhow::{Context, Result};
+use ignore::{
+    Match, WalkBuilder,
+    gitignore::{Gitignore, GitignoreBuilder},
+};
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+};
+use tracing::warn;
+
+/// files whose patterns are honored in every directory of a watched root.
+/// later files take precedence over earlier ones in the same directory
+pub const IGNORE_FILES: [&str; 3] = [".gitignore", ".ignore", ".timeitignore"];
+
+/// decides which paths of a watched root are ignored, with gitignore
+/// semantics. every directory can have its own ignore files and the
+/// deepest one that has an opinion about a path wins. the ignore patterns
+/// of the config act like a .gitignore at the root with the lowest precedence
+#[derive(Debug)]
+pub struct IgnoreRules {
+    root: PathBuf,
+    config_rules: Gitignore,
+    dir_rules: HashMap<PathBuf, Gitignore>,
+}
+
+impl IgnoreRules {
+    /// reads every ignore file under root. directories that are already
+    /// ignored are not searched for ignore files
+    pub fn new(root: &Path, patterns: &[String]) -> Result<Self> {
+        let mut rules = Self {
+            root: root.to_path_buf(),
+            config_rules: build_config_rules(root, patterns)?,
+            dir_rules: HashMap::new(),
+        };
+
+        let mut walker = WalkBuilder::new(root);
+        walker.hidden(false).require_git(false);
+        for name in IGNORE_FILES {
+            walker.add_custom_ignore_filename(name);
+        }
+        for entry in walker.build().flatten() {
+            if entry.file_type().is_some_and(|kind| kind.is_dir())
+                && !rules.is_ignored(entry.path(), true)
+            {
+                rules.reload_dir(entry.path());
+            }
+        }
+        Ok(rules)
+    }
+
+    /// checks whether the ignore rules cover the given path.
+    /// version control directories are always ignored
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if path.components().any(|component| {
+            matches!(component, Component::Normal(name) if name == ".git" || name == ".hg" || name == ".svn")
+        }) {
+            return true;
+        }
+        if !path.starts_with(&self.root) {
+            return false;
+        }
+
+        // from the deepest directory up to the root
+        for dir in path.ancestors().skip(1) {
+            if let Some(rules) = self.dir_rules.get(dir) {
+                match rules.matched_path_or_any_parents(path, is_dir) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => {}
+                }
+            }
+            if dir == self.root {
+                break;
+            }
+        }
+        self.config_rules
+            .matched_path_or_any_parents(path, is_dir)
+            .is_ignore()
+    }
+
+    /// should be called when the given path changed. if it is an ignore file,
+    /// the rules of its directory are read again
+    pub fn handle_change(&mut self, path: &Path) {
+        let is_ignore_file = path
+            .file_name()
+            .is_some_and(|name| IGNORE_FILES.iter().any(|file| name == *file));
+        if is_ignore_file && let Some(dir) = path.parent() {
+            self.reload_dir(dir);
+        }
+    }
+
+    /// rebuilds the rules of a single directory from its ignore files
+    fn reload_dir(&mut self, dir: &Path) {
+        let mut builder = GitignoreBuilder::new(dir);
+        let mut found = false;
+        for name in IGNORE_FILES {
+            let file = dir.join(name);
+            if file.is_file() {
+                found = true;
+                if let Some(e) = builder.add(&file) {
+                    warn!("failed to read {}: {e}", file.display());
+                }
+            }
+        }
+
+        match builder.build() {
+            Ok(rules) if found && !rules.is_empty() => {
+                self.dir_rules.insert(dir.to_path_buf(), rules);
+            }
+            Ok(_) => {
+                self.dir_rules.remove(dir);
+            }
+            Err(e) => warn!("invalid ignore rules in {}: {e}", dir.display()),
+        }
+    }
+}
+
+/// builds the rules of the ignore patterns of the config, anchored at root
+fn build_config_rules(root: &Path, patterns: &[String]) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(root);
+    for pattern in patterns {
+        builder
+            .add_line(None, pattern)
+            .with_context(|| format!("invalid ignore pattern {pattern:?}"))?;
+    }
+    builder.build().context("failed to build ignore patterns")
+}
+
+/// checks that every pattern is a valid gitignore pattern
+pub fn validate_patterns(patterns: &[String]) -> Result<()> {
+    build_config_rules(Path::new(""), patterns).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, content: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn ignore_files_of_every_directory_are_honored() {
+        let root = std::env::temp_dir().join(format!("timeit-ignore-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        write(&root.join(".gitignore"), "*.log\n/build/\n");
+        write(&root.join("src/.ignore"), "!keep.log\ngenerated/\n");
+        write(&root.join("docs/.timeitignore"), "*.md\n");
+        write(&root.join("src/main.rs"), "");
+
+        let mut rules = IgnoreRules::new(&root, &["*.tmp".to_string()]).unwrap();
+        let ignored = |rules: &IgnoreRules, path: &str| rules.is_ignored(&root.join(path), false);
+
+        assert!(ignored(&rules, "app.log"));
+        assert!(ignored(&rules, "src/other.log"));
+        // a negation deeper down wins over the root
+        assert!(!ignored(&rules, "src/keep.log"));
+        // anchored to the root
+        assert!(ignored(&rules, "build/out.rs"));
+        assert!(!ignored(&rules, "src/build/out.rs"));
+        assert!(ignored(&rules, "src/generated/out.rs"));
+        assert!(!ignored(&rules, "generated/out.rs"));
+        assert!(ignored(&rules, "docs/guide.md"));
+        assert!(!ignored(&rules, "guide.md"));
+        // the config patterns and version control
+        assert!(ignored(&rules, "src/scratch.tmp"));
+        assert!(ignored(&rules, ".git/config"));
+        assert!(!ignored(&rules, "src/main.rs"));
+
+        // edited while watching
+        let timeitignore = root.join("docs/.timeitignore");
+        write(&timeitignore, "*.txt\n");
+        rules.handle_change(&timeitignore);
+        assert!(!ignored(&rules, "docs/guide.md"));
+        assert!(ignored(&rules, "docs/notes.txt"));
+
+        std::fs::remove_file(&timeitignore).unwrap();
+        rules.handle_change(&timeitignore);
+        assert!(!ignored(&rules, "docs/notes.txt"));
+
+        // other files don't make it read anything
+        write(&root.join("src/.ignore"), "*.rs\n");
+        rules.handle_change(&root.join("src/main.rs"));
+        assert!(!ignored(&rules, "src/main.rs"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
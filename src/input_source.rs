@@ -0,0 +1,364 @@
+use anyhow::{Result, anyhow, bail};
+use evdev::{
+    AbsoluteAxisCode, BusType, Device, EventSummary, EventType, KeyCode, PropType,
+    RelativeAxisCode, SynchronizationCode,
+};
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+use tracing::{debug, info, warn};
+
+use crate::config::InputBackend;
+
+/// where the evdev devices are
+const INPUT_DIR: &str = "/dev/input";
+
+/// what kind of activity an input was
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    Key,
+    Click,
+    Scroll,
+    MouseMove,
+}
+
+/// a single key press, click, scroll or mouse move
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Input {
+    pub kind: InputKind,
+    /// how far the mouse moved, in pixels or the units of the touchpad
+    pub delta: (f64, f64),
+    /// made up by another program, like a virtual keyboard or a remote
+    /// desktop, instead of coming from a device. only evdev can tell
+    pub synthetic: bool,
+}
+
+impl Input {
+    pub fn new(kind: InputKind) -> Self {
+        Self {
+            kind,
+            delta: (0.0, 0.0),
+            synthetic: false,
+        }
+    }
+
+    pub fn mouse_move(dx: f64, dy: f64) -> Self {
+        Self {
+            delta: (dx, dy),
+            ..Self::new(InputKind::MouseMove)
+        }
+    }
+
+    pub fn with_synthetic(mut self, synthetic: bool) -> Self {
+        self.synthetic = synthetic;
+        self
+    }
+}
+
+/// called for every key press, click, scroll and mouse move
+pub type InputCallback = Box<dyn FnMut(Input) + Send>;
+
+/// somewhere keyboard and mouse activity comes from
+pub trait InputSource: Send {
+    fn name(&self) -> &'static str;
+
+    /// calls on_input for every input. blocks until the source fails,
+    /// so it must run on a thread of its own
+    fn listen(self: Box<Self>, on_input: InputCallback) -> Result<()>;
+}
+
+/// opens the input source of the backend. auto uses evdev under wayland and
+/// without any display, where rdev can't see anything, and rdev otherwise
+pub fn open(backend: InputBackend) -> Result<Box<dyn InputSource>> {
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+    let x11 = std::env::var_os("DISPLAY").is_some();
+    match resolve(backend, wayland, x11) {
+        InputBackend::Evdev => match EvdevSource::open(Path::new(INPUT_DIR)) {
+            Ok(source) => Ok(Box::new(source)),
+            // xwayland still lets rdev see the input of x11 windows
+            Err(e) if backend == InputBackend::Auto && x11 => {
+                warn!("falling back to rdev: {e:#}");
+                Ok(Box::new(RdevSource))
+            }
+            Err(e) => Err(e),
+        },
+        InputBackend::Logind | InputBackend::Command => {
+            bail!("the {backend:?} backend only tells whether the user is idle")
+        }
+        InputBackend::Auto | InputBackend::Rdev => Ok(Box::new(RdevSource)),
+    }
+}
+
+/// the backend that auto stands for
+fn resolve(backend: InputBackend, wayland: bool, x11: bool) -> InputBackend {
+    match backend {
+        InputBackend::Auto if wayland || !x11 => InputBackend::Evdev,
+        InputBackend::Auto => InputBackend::Rdev,
+        backend => backend,
+    }
+}
+
+/// global x11 input through rdev
+#[derive(Debug)]
+pub struct RdevSource;
+
+impl InputSource for RdevSource {
+    fn name(&self) -> &'static str {
+        "rdev"
+    }
+
+    fn listen(self: Box<Self>, mut on_input: InputCallback) -> Result<()> {
+        // rdev only knows where the pointer is, not how far it moved
+        let mut position = None;
+        rdev::listen(move |event| {
+            let input = match event.event_type {
+                rdev::EventType::KeyPress(_) => Input::new(InputKind::Key),
+                rdev::EventType::ButtonPress(_) => Input::new(InputKind::Click),
+                rdev::EventType::Wheel { .. } => Input::new(InputKind::Scroll),
+                rdev::EventType::MouseMove { x, y } => match position.replace((x, y)) {
+                    Some((last_x, last_y)) => Input::mouse_move(x - last_x, y - last_y),
+                    None => return,
+                },
+                _ => return,
+            };
+            on_input(input)
+        })
+        .map_err(|e| anyhow!("rdev failed to listen: {e:?}"))
+    }
+}
+
+/// reads the kernel's input devices directly, which works under wayland and
+/// on a plain tty. the user needs to be allowed to read /dev/input/event*,
+/// usually by being in the input group
+#[derive(Debug)]
+pub struct EvdevSource {
+    devices: Vec<(PathBuf, Device)>,
+}
+
+impl EvdevSource {
+    /// opens every keyboard, mouse and touchpad in the directory. fails with
+    /// a hint on how to get access if none could be opened
+    pub fn open(dir: &Path) -> Result<Self> {
+        let entries =
+            std::fs::read_dir(dir).map_err(|e| anyhow!("failed to read {}: {e}", dir.display()))?;
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("event"))
+            })
+            .collect();
+        paths.sort();
+
+        let mut devices = Vec::new();
+        let mut denied = 0;
+        for path in paths {
+            match Device::open(&path) {
+                Ok(device) if is_user_input(&device) => {
+                    debug!(
+                        "reading input of {} ({})",
+                        path.display(),
+                        device.name().unwrap_or("unnamed")
+                    );
+                    devices.push((path, device));
+                }
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::PermissionDenied => denied += 1,
+                Err(e) => debug!("failed to open {}: {e}", path.display()),
+            }
+        }
+
+        if devices.is_empty() {
+            if denied > 0 {
+                bail!(
+                    "not allowed to read the {denied} input devices in {}. add yourself to \
+                     the group that owns them (`sudo usermod -aG input $USER`) and log in again",
+                    dir.display()
+                );
+            }
+            bail!("found no keyboard or mouse in {}", dir.display());
+        }
+        if denied > 0 {
+            warn!("not allowed to read {denied} of the input devices, their input is missed");
+        }
+        Ok(Self { devices })
+    }
+}
+
+impl InputSource for EvdevSource {
+    fn name(&self) -> &'static str {
+        "evdev"
+    }
+
+    fn listen(self: Box<Self>, mut on_input: InputCallback) -> Result<()> {
+        info!("reading input from {} devices", self.devices.len());
+        // reading blocks, so every device gets a thread of its own
+        let (sender, inputs) = mpsc::channel();
+        for (path, mut device) in self.devices {
+            let sender = sender.clone();
+            std::thread::spawn(move || {
+                // uinput devices, made by programs like ydotool
+                let synthetic = device.input_id().bus_type() == BusType::BUS_VIRTUAL;
+                let mut reader = EventReader::new(synthetic);
+                loop {
+                    let events = match device.fetch_events() {
+                        Ok(events) => events,
+                        Err(e) => {
+                            // unplugged devices end up here too
+                            debug!("stopped reading {}: {e}", path.display());
+                            return;
+                        }
+                    };
+                    for input in events.filter_map(|event| reader.read(event)) {
+                        if sender.send(input).is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        for input in inputs {
+            on_input(input);
+        }
+        bail!("every input device is gone")
+    }
+}
+
+/// keyboards, mice, touchpads and the like. accelerometers report
+/// movement all the time without anyone touching them
+fn is_user_input(device: &Device) -> bool {
+    let events = device.supported_events();
+    let input = events.contains(EventType::KEY)
+        || events.contains(EventType::RELATIVE)
+        || events.contains(EventType::ABSOLUTE);
+    input && !device.properties().contains(PropType::ACCELEROMETER)
+}
+
+/// turns the events of a device into inputs. a device reports the axes of
+/// a single move as separate events, followed by a SYN_REPORT
+#[derive(Debug, Default)]
+struct EventReader {
+    synthetic: bool,
+    // movement since the last SYN_REPORT
+    moved: Option<(f64, f64)>,
+    // last absolute position of touchpads and tablets, per axis
+    position: (Option<i32>, Option<i32>),
+}
+
+impl EventReader {
+    fn new(synthetic: bool) -> Self {
+        Self {
+            synthetic,
+            ..Self::default()
+        }
+    }
+
+    fn read(&mut self, event: evdev::InputEvent) -> Option<Input> {
+        let input = match event.destructure() {
+            // releases aren't any more activity than the press was
+            EventSummary::Key(_, _, 0) => return None,
+            EventSummary::Key(_, key, _) => Input::new(key_kind(key)),
+            EventSummary::RelativeAxis(_, RelativeAxisCode::REL_X, dx) => {
+                return self.moved(dx as f64, 0.0);
+            }
+            EventSummary::RelativeAxis(_, RelativeAxisCode::REL_Y, dy) => {
+                return self.moved(0.0, dy as f64);
+            }
+            EventSummary::RelativeAxis(
+                _,
+                RelativeAxisCode::REL_WHEEL | RelativeAxisCode::REL_HWHEEL,
+                _,
+            ) => Input::new(InputKind::Scroll),
+            EventSummary::AbsoluteAxis(_, AbsoluteAxisCode::ABS_X, x) => {
+                let dx = self.position.0.replace(x).map_or(0, |last| x - last);
+                return self.moved(dx as f64, 0.0);
+            }
+            EventSummary::AbsoluteAxis(_, AbsoluteAxisCode::ABS_Y, y) => {
+                let dy = self.position.1.replace(y).map_or(0, |last| y - last);
+                return self.moved(0.0, dy as f64);
+            }
+            EventSummary::Synchronization(_, SynchronizationCode::SYN_REPORT, _) => {
+                let (dx, dy) = self.moved.take()?;
+                Input::mouse_move(dx, dy)
+            }
+            _ => return None,
+        };
+        Some(input.with_synthetic(self.synthetic))
+    }
+
+    // adds to the move that the next SYN_REPORT ends
+    fn moved(&mut self, dx: f64, dy: f64) -> Option<Input> {
+        let (x, y) = self.moved.get_or_insert((0.0, 0.0));
+        *x += dx;
+        *y += dy;
+        None
+    }
+}
+
+/// buttons of mice and touchpads are keys as well
+fn key_kind(key: KeyCode) -> InputKind {
+    let mouse_buttons = KeyCode::BTN_LEFT.code()..=KeyCode::BTN_TASK.code();
+    let touch = KeyCode::BTN_TOOL_PEN.code()..=KeyCode::BTN_TOOL_QUADTAP.code();
+    if mouse_buttons.contains(&key.code()) || touch.contains(&key.code()) {
+        InputKind::Click
+    } else {
+        InputKind::Key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_prefers_evdev_without_x11() {
+        let auto = InputBackend::Auto;
+        assert_eq!(resolve(auto, false, true), InputBackend::Rdev);
+        assert_eq!(resolve(auto, true, true), InputBackend::Evdev);
+        assert_eq!(resolve(auto, false, false), InputBackend::Evdev);
+        // an explicit choice is kept
+        assert_eq!(resolve(InputBackend::Rdev, true, false), InputBackend::Rdev);
+    }
+
+    #[test]
+    fn mouse_buttons_are_mouse_input() {
+        assert_eq!(key_kind(KeyCode::KEY_A), InputKind::Key);
+        assert_eq!(key_kind(KeyCode::KEY_ENTER), InputKind::Key);
+        assert_eq!(key_kind(KeyCode::BTN_LEFT), InputKind::Click);
+        assert_eq!(key_kind(KeyCode::BTN_TOUCH), InputKind::Click);
+    }
+
+    #[test]
+    fn axes_of_a_move_are_one_input() {
+        use evdev::{InputEvent, RelativeAxisEvent, SynchronizationEvent};
+
+        let x = |dx| *RelativeAxisEvent::new(RelativeAxisCode::REL_X, dx);
+        let y = |dy| *RelativeAxisEvent::new(RelativeAxisCode::REL_Y, dy);
+        let report = || *SynchronizationEvent::new(SynchronizationCode::SYN_REPORT, 0);
+        let events: [InputEvent; 6] = [x(3), y(-4), report(), report(), x(1), report()];
+
+        let mut reader = EventReader::new(true);
+        let inputs: Vec<Input> = events
+            .into_iter()
+            .filter_map(|event| reader.read(event))
+            .collect();
+        assert_eq!(
+            inputs,
+            [
+                Input::mouse_move(3.0, -4.0).with_synthetic(true),
+                Input::mouse_move(1.0, 0.0).with_synthetic(true)
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_directory_fails() {
+        assert!(EvdevSource::open(Path::new("/nonexistent/input")).is_err());
+    }
+}
@@ -1,22 +1,49 @@
-use anyhow::{Context, Result};
-use rdev::{Event, listen};
 use std::{
-    sync::{Arc, RwLock},
-    time::{Duration, SystemTime},
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    sync::{
+        broadcast::{self, Receiver, Sender},
+        mpsc::{UnboundedReceiver, UnboundedSender},
+    },
+    time::MissedTickBehavior,
+};
+use tracing::{error, info, instrument, trace, warn};
+
+use crate::{
+    activity_validator::ActivityValidator,
+    clock::{SharedClock, SystemClock},
+    config::{InputConfig, ValidationConfig},
+    idle_source::IdleSource,
+    input_source::{Input, InputKind, InputSource},
+    tracking_event::TrackingEvents,
 };
-use tokio::sync::broadcast::{self, Receiver, Sender};
-use tracing::error;
 
 // input watcher should look out for keyboard and mouse inputs
 // it should filter for "any" event captured by rdev. it must also
 // keep track of activity time and idle time and
 //
 
-#[derive(Debug, Clone)]
+/// how often the inputs are coalesced into an ActivityPulse
+pub const PULSE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum ActivityEvent {
-    KeyboardActivity { time_stamp: SystemTime },
-    MouseActivity { time_stamp: SystemTime },
-    IdleDetected { duration: Duration },
+    /// every valid input of the last pulse interval
+    ActivityPulse {
+        keys: u64,
+        mouse_moves: u64,
+        clicks: u64,
+        scrolls: u64,
+        last_input: SystemTime,
+    },
+    IdleDetected {
+        duration: Duration,
+    },
     ActivityResumed,
 }
 
@@ -27,7 +54,6 @@ pub struct ActivityState {
     pub is_idle: bool,
 }
 
-#[allow(dead_code)]
 impl ActivityState {
     pub fn new() -> Self {
         Self {
@@ -36,53 +62,135 @@ impl ActivityState {
         }
     }
 
-    /// checks to see if there has been some sort of keyboard or mouse
-    /// activity within the specified duration
-    pub fn is_recently_active(&self, within: Duration) -> bool {
-        if let Some(activity) = self.last_activity {
-            if let Ok(elapsed) = activity.elapsed() {
-                return elapsed <= within;
-            }
+    /// returns the time dif between now and last activity
+    pub fn time_since_last_activity(&self, now: SystemTime) -> Option<Duration> {
+        self.last_activity
+            .and_then(|time| now.duration_since(time).ok())
+    }
+
+    // moves the last activity forward, never back
+    fn saw_activity(&mut self, at: SystemTime) {
+        if self.last_activity.is_none_or(|last| last < at) {
+            self.last_activity = Some(at);
         }
-        false
     }
+}
 
-    /// returns the time dif between now and last activity
-    pub fn time_since_last_activity(&self) -> Option<Duration> {
-        self.last_activity.and_then(|time| time.elapsed().ok())
+/// the inputs since the last pulse. touched by every single key press and
+/// mouse move, so it's only atomics and input never waits for a lock
+#[derive(Debug, Default)]
+struct InputCounters {
+    // microseconds since unix epoch, 0 before the first input
+    last_input: AtomicU64,
+    keys: AtomicU64,
+    mouse_moves: AtomicU64,
+    clicks: AtomicU64,
+    scrolls: AtomicU64,
+}
+
+impl InputCounters {
+    fn record(&self, kind: InputKind, now: SystemTime) {
+        let micros = now
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_micros() as u64)
+            .unwrap_or_default();
+        // several sources may race, the latest input wins
+        self.last_input.fetch_max(micros, Ordering::Relaxed);
+        let counter = match kind {
+            InputKind::Key => &self.keys,
+            InputKind::MouseMove => &self.mouse_moves,
+            InputKind::Click => &self.clicks,
+            InputKind::Scroll => &self.scrolls,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn last_input(&self) -> Option<SystemTime> {
+        match self.last_input.load(Ordering::Relaxed) {
+            0 => None,
+            micros => Some(UNIX_EPOCH + Duration::from_micros(micros)),
+        }
+    }
+
+    /// the inputs since the last call as an ActivityPulse, None without any
+    fn take(&self) -> Option<ActivityEvent> {
+        let pulse = ActivityEvent::ActivityPulse {
+            keys: self.keys.swap(0, Ordering::Relaxed),
+            mouse_moves: self.mouse_moves.swap(0, Ordering::Relaxed),
+            clicks: self.clicks.swap(0, Ordering::Relaxed),
+            scrolls: self.scrolls.swap(0, Ordering::Relaxed),
+            last_input: self.last_input()?,
+        };
+        match pulse {
+            ActivityEvent::ActivityPulse {
+                keys: 0,
+                mouse_moves: 0,
+                clicks: 0,
+                scrolls: 0,
+                ..
+            } => None,
+            pulse => Some(pulse),
+        }
     }
 }
 
-// Tracks input activity
-// for now it considers all activities as valid
-// but we should add threshold for mouse movements
-// and other validation algos
+// Tracks input activity. what counts as activity is up to the
+// ActivityValidator of the input source
 #[derive(Debug, Clone)]
 pub struct InputMonitor {
     state: Arc<RwLock<ActivityState>>,
+    // raw input, folded into state by pulse() and check_idle()
+    inputs: Arc<InputCounters>,
     pub event_sender: Sender<ActivityEvent>,
-    idle_threshold: Duration,
+    // can be changed while running when the config is reloaded
+    idle_threshold: Arc<RwLock<Duration>>,
+    validation: ValidationConfig,
+    clock: SharedClock,
 }
 
-#[allow(dead_code)]
 impl InputMonitor {
-    /// takes in the duration for idle threshold and a sender channel for events
-    pub fn new() -> (Self, Receiver<ActivityEvent>) {
+    /// creates a monitor using the given input settings and
+    /// returns it with the receiver channel of its events
+    pub fn new(config: &InputConfig) -> (Self, Receiver<ActivityEvent>) {
         let (tx, rx) = broadcast::channel::<ActivityEvent>(100);
         (
             Self {
                 state: Arc::new(RwLock::new(ActivityState::new())),
+                inputs: Arc::new(InputCounters::default()),
                 event_sender: tx,
-                idle_threshold: Duration::from_secs(20),
+                idle_threshold: Arc::new(RwLock::new(config.idle_threshold())),
+                validation: config.validation.clone(),
+                clock: SystemClock::shared(),
             },
             rx,
         )
     }
 
-    /// returns ActivityState of the object
+    /// makes the monitor take the time from the given clock instead of the system's
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// changes how long there must be no activity before the user is idle
+    pub fn set_idle_threshold(&self, threshold: Duration) {
+        *self
+            .idle_threshold
+            .write()
+            .expect("failed to get a write lock on idle threshold") = threshold;
+    }
+
+    /// returns ActivityState of the object, including input that hasn't
+    /// been pulsed yet
     pub fn get_state(&self) -> Option<ActivityState> {
         match self.state.read() {
-            Ok(event) => Some(event.clone()),
+            Ok(state) => {
+                let mut state = state.clone();
+                if let Some(at) = self.inputs.last_input() {
+                    state.saw_activity(at);
+                }
+                Some(state)
+            }
             Err(e) => {
                 error!("Failed to get activity state: {e}");
                 None
@@ -90,131 +198,435 @@ impl InputMonitor {
         }
     }
 
-    /// if there has been some keyboard activity, this method must be called
-    /// it will set the last activity to now, is_idle to false and send a
-    /// ActivityEvent::ActivityResumed through the channel
-    /// if is_idle is already false, a KeyboardActivity will be sent back
-    pub fn handle_keyboard_event(&self) -> Result<()> {
-        println!("inside keyboard handler method");
+    /// must be called for every valid input. only notes the time and
+    /// counts the input, the next pulse tells everyone else about it
+    pub fn handle_input(&self, kind: InputKind) {
+        self.inputs.record(kind, self.clock.now());
+    }
 
-        let now = SystemTime::now();
+    /// sends the inputs since the last pulse as a single ActivityPulse,
+    /// preceded by ActivityResumed if the user was idle. returns the pulse,
+    /// or None if there was no input
+    pub fn pulse(&self) -> Option<ActivityEvent> {
+        let pulse = self.inputs.take()?;
         let mut state = self
             .state
             .write()
-            .expect("failed to get a read lock on state");
-        state.last_activity = Some(now);
-
+            .expect("failed to get a write lock on state");
+        if let ActivityEvent::ActivityPulse { last_input, .. } = pulse {
+            state.saw_activity(last_input);
+        }
         if state.is_idle {
             state.is_idle = false;
-            println!("activity resumed");
-            self.event_sender
-                .send(ActivityEvent::ActivityResumed)
-                .context("failed to send activity resumed through channel")?;
+            let _ = self.event_sender.send(ActivityEvent::ActivityResumed);
         }
-
-        println!("keyboard activity detected");
-        self.event_sender
-            .send(ActivityEvent::KeyboardActivity { time_stamp: now })
-            .context("failed to send keyboard event")?;
-        Ok(())
+        let _ = self.event_sender.send(pulse.clone());
+        Some(pulse)
     }
 
-    /// if a mouse activity is detected, last activity will be set to now()
-    /// then if is_idle is true, we'll set it to false and send back an
-    /// ActivityResumed event, otherwise a MouseActivity event will be returned
-    pub fn handle_mouse_event(&self) -> Result<()> {
-        println!("inside keyboard handler method");
-        let now = SystemTime::now();
-        let mut state = self
-            .state
-            .write()
-            .expect("failed to get a read lock on state");
-        state.last_activity = Some(now);
-
-        if state.is_idle {
-            state.is_idle = false;
-
-            println!("activity resumed");
-            self.event_sender
-                .send(ActivityEvent::ActivityResumed)
-                .context("failed to send activity resumed through channel")?;
+    /// pulses every interval, for as long as the monitor is around
+    pub async fn start_pulses(self: Arc<Self>, interval: Duration) {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            self.pulse();
         }
-
-        println!("mouse activity detected");
-        self.event_sender
-            .send(ActivityEvent::MouseActivity { time_stamp: now })
-            .context("failed to send mouse event")?;
-        Ok(())
     }
 
-    pub async fn start_activity_monitoring(self: Arc<Self>) {
-        let input_monitor = Arc::new(RwLock::new(self));
-
-        let callback = move |event: Event| match event.event_type {
-            rdev::EventType::KeyPress(_key) | rdev::EventType::KeyRelease(_key) => {
-                let lock = input_monitor
-                    .read()
-                    .expect("failed to get a read lock on input monitor");
-                lock.handle_keyboard_event()
-                    .expect("failed to handle keyboard event");
+    /// listens for keyboard and mouse events of the source and counts the
+    /// valid ones. blocks until the source fails, so it must run on a thread
+    /// of its own
+    pub fn start_activity_monitoring(self: Arc<Self>, source: Box<dyn InputSource>) {
+        let name = source.name();
+        let mut validator = ActivityValidator::new(self.validation.clone());
+        let on_input = Box::new(move |input: Input| {
+            if validator.validate(&input, self.clock.instant()) {
+                self.handle_input(input.kind);
             }
-            rdev::EventType::MouseMove { x: _, y: _ } => {
-                let lock = input_monitor
-                    .read()
-                    .expect("failed to get a read lock on input monitor");
-                lock.handle_mouse_event().unwrap()
-            }
-            _ => {}
-        };
-
-        if let Err(e) = listen(callback) {
-            println!("error: {e:?}");
+        });
+        if let Err(e) = source.listen(on_input) {
+            error!("stopped listening for input with {name}: {e:#}");
         }
     }
 
-    /// will listen for events as they happen. these events are received through receiver channel
-    pub async fn receive_events(self: Arc<Self>, mut receiver: Receiver<ActivityEvent>) {
+    /// will listen for events as they happen. these events are received through receiver channel.
+    /// every activity is forwarded to the manager through the tracking channel
+    #[instrument(name = "input", skip_all)]
+    pub async fn receive_events(
+        self: Arc<Self>,
+        mut receiver: Receiver<ActivityEvent>,
+        tracking_sender: UnboundedSender<TrackingEvents>,
+    ) {
         while let Ok(event) = receiver.recv().await {
             match event {
-                ActivityEvent::KeyboardActivity { time_stamp } => {
-                    println!("Keyboard activity at {time_stamp:?}");
-                }
-                ActivityEvent::MouseActivity { time_stamp } => {
-                    println!("Mouse activity at {time_stamp:?}");
+                ActivityEvent::ActivityPulse {
+                    keys,
+                    mouse_moves,
+                    clicks,
+                    scrolls,
+                    ..
+                } => {
+                    trace!(keys, mouse_moves, clicks, scrolls, "activity");
+                    let _ = tracking_sender.send(TrackingEvents::UserActive);
                 }
                 ActivityEvent::IdleDetected { duration } => {
-                    println!("User idle for {duration:?}");
+                    info!(idle_secs = duration.as_secs(), "user idle");
+                    let _ = tracking_sender.send(TrackingEvents::UserIdle { duration });
                 }
                 ActivityEvent::ActivityResumed => {
-                    println!("User activity resumed");
+                    info!("user activity resumed");
+                    let _ = tracking_sender.send(TrackingEvents::UserActive);
                 }
             }
         }
     }
+    /// forwards tracking events to the manager, counting saves and focus
+    /// changes as activity. without an input or an idle source the files
+    /// are all there is to tell whether the user is still around
+    #[instrument(name = "file_activity", skip_all)]
+    pub async fn count_file_activity(
+        self: Arc<Self>,
+        mut events: UnboundedReceiver<TrackingEvents>,
+        tracking_sender: UnboundedSender<TrackingEvents>,
+    ) {
+        while let Some(event) = events.recv().await {
+            // opens and closes come from compilers and search tools as well
+            if matches!(
+                event,
+                TrackingEvents::FileModified { .. } | TrackingEvents::FileFocused { .. }
+            ) {
+                self.record_activity_at(self.clock.now());
+            }
+            if tracking_sender.send(event).is_err() {
+                // manager is gone, nothing left to report to
+                return;
+            }
+        }
+    }
+
     /// spawns a thread inside which idle activity is detected.
     /// if a file is not modified or no keyboard/mouse activity
     /// is detected within idle_threshold, activity state will be
     /// set to idle and an IdleDetected event will be sent back
     /// monitor_tick parameter will dictate the frequency of this check
     pub async fn start_idle_monitoring(self: Arc<Self>, monitor_tick: u64) {
-        let state = self.state.clone();
-        let event_sender = self.event_sender.clone();
-        let idle_threshold = self.idle_threshold;
-
         loop {
             tokio::time::sleep(Duration::from_secs(monitor_tick)).await;
+            self.check_idle();
+        }
+    }
 
-            let mut state_guard = state.write().expect("failed to get a write lock on state");
-            if let Some(activity) = state_guard.last_activity {
-                if let Ok(elapsed) = activity.elapsed() {
-                    if elapsed >= idle_threshold && !state_guard.is_idle {
-                        state_guard.is_idle = true;
-                        event_sender
-                            .send(ActivityEvent::IdleDetected { duration: elapsed })
-                            .expect("failed to send idle detection through channel");
-                    }
+    /// for sources that only know when the user was last active. moves the
+    /// last activity forward to at and sends ActivityResumed if the user was
+    /// idle and at is within the idle threshold
+    pub fn record_activity_at(&self, at: SystemTime) {
+        let mut state = self
+            .state
+            .write()
+            .expect("failed to get a write lock on state");
+        if state.last_activity.is_some_and(|last| last >= at) {
+            return;
+        }
+        state.last_activity = Some(at);
+
+        let idle_threshold = *self
+            .idle_threshold
+            .read()
+            .expect("failed to get a read lock on idle threshold");
+        let idle_for = self.clock.now().duration_since(at).unwrap_or_default();
+        if state.is_idle && idle_for < idle_threshold {
+            state.is_idle = false;
+            let _ = self.event_sender.send(ActivityEvent::ActivityResumed);
+        }
+    }
+
+    /// asks the idle source when the user was last active every tick
+    /// seconds, then checks for idle like start_idle_monitoring does
+    #[instrument(name = "idle_source", skip_all)]
+    pub async fn start_idle_source(self: Arc<Self>, source: IdleSource, tick: u64) {
+        loop {
+            match source.last_activity(self.clock.now()).await {
+                Ok(at) => {
+                    self.record_activity_at(at);
+                    self.check_idle();
                 }
+                Err(e) => warn!("failed to ask {} for the idle time: {e:#}", source.name()),
+            }
+            tokio::time::sleep(Duration::from_secs(tick)).await;
+        }
+    }
+
+    /// marks the state as idle if there hasn't been any activity within
+    /// idle_threshold and sends an IdleDetected event. returns for how
+    /// long the user has been idle if it just became idle
+    pub fn check_idle(&self) -> Option<Duration> {
+        let mut state = self
+            .state
+            .write()
+            .expect("failed to get a write lock on state");
+        // input since the last pulse counts as well. resuming is left to
+        // the next pulse
+        if let Some(at) = self.inputs.last_input() {
+            state.saw_activity(at);
+        }
+        let elapsed = state.time_since_last_activity(self.clock.now())?;
+        let idle_threshold = *self
+            .idle_threshold
+            .read()
+            .expect("failed to get a read lock on idle threshold");
+        if elapsed < idle_threshold || state.is_idle {
+            return None;
+        }
+
+        state.is_idle = true;
+        // nobody listening only means nobody is interested anymore
+        let _ = self
+            .event_sender
+            .send(ActivityEvent::IdleDetected { duration: elapsed });
+        Some(elapsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, MockClock};
+
+    #[test]
+    fn idle_is_detected_once_the_threshold_is_reached() {
+        let clock = MockClock::new();
+        let (monitor, mut receiver) = InputMonitor::new(&InputConfig::default());
+        let monitor = monitor.with_clock(clock.shared());
+
+        monitor.handle_input(InputKind::Key);
+        clock.advance(Duration::from_secs(19));
+        assert_eq!(monitor.check_idle(), None);
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(monitor.check_idle(), Some(Duration::from_secs(20)));
+        // only reported once
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(monitor.check_idle(), None);
+
+        let mut idle = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            if let ActivityEvent::IdleDetected { duration } = event {
+                idle.push(duration);
             }
         }
+        assert_eq!(idle, [Duration::from_secs(20)]);
+    }
+
+    #[test]
+    fn activity_resets_the_idle_timer() {
+        let clock = MockClock::new();
+        let (monitor, _receiver) = InputMonitor::new(&InputConfig::default());
+        let monitor = monitor.with_clock(clock.shared());
+
+        monitor.handle_input(InputKind::MouseMove);
+        clock.advance(Duration::from_secs(15));
+        monitor.handle_input(InputKind::Key);
+        clock.advance(Duration::from_secs(15));
+        assert_eq!(monitor.check_idle(), None);
+
+        let state = monitor.get_state().unwrap();
+        assert_eq!(
+            state.time_since_last_activity(clock.now()),
+            Some(Duration::from_secs(15))
+        );
+    }
+
+    #[test]
+    fn idle_sources_report_the_last_activity() {
+        let clock = MockClock::new();
+        let (monitor, mut receiver) = InputMonitor::new(&InputConfig::default());
+        let monitor = monitor.with_clock(clock.shared());
+
+        // the desktop says the user left 30s ago
+        clock.advance(Duration::from_secs(60));
+        monitor.record_activity_at(clock.now() - Duration::from_secs(30));
+        assert_eq!(monitor.check_idle(), Some(Duration::from_secs(30)));
+
+        // an older time changes nothing, a recent one ends the idle time
+        monitor.record_activity_at(clock.now() - Duration::from_secs(40));
+        assert!(monitor.get_state().unwrap().is_idle);
+        monitor.record_activity_at(clock.now());
+        assert!(!monitor.get_state().unwrap().is_idle);
+
+        let mut events = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            events.push(event);
+        }
+        assert!(matches!(
+            events[..],
+            [
+                ActivityEvent::IdleDetected { .. },
+                ActivityEvent::ActivityResumed
+            ]
+        ));
+    }
+
+    #[test]
+    fn inputs_are_coalesced_into_pulses() {
+        let clock = MockClock::new();
+        let (monitor, mut receiver) = InputMonitor::new(&InputConfig::default());
+        let monitor = monitor.with_clock(clock.shared());
+
+        assert_eq!(monitor.pulse(), None);
+        for _ in 0..1000 {
+            monitor.handle_input(InputKind::Key);
+            monitor.handle_input(InputKind::MouseMove);
+            monitor.handle_input(InputKind::MouseMove);
+        }
+        clock.advance(Duration::from_millis(300));
+        monitor.handle_input(InputKind::Key);
+
+        let pulse = ActivityEvent::ActivityPulse {
+            keys: 1001,
+            mouse_moves: 2000,
+            clicks: 0,
+            scrolls: 0,
+            last_input: clock.now(),
+        };
+        assert_eq!(monitor.pulse(), Some(pulse.clone()));
+        assert_eq!(monitor.pulse(), None);
+        assert_eq!(receiver.try_recv().unwrap(), pulse);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn a_pulse_ends_the_idle_time() {
+        let clock = MockClock::new();
+        let (monitor, mut receiver) = InputMonitor::new(&InputConfig::default());
+        let monitor = monitor.with_clock(clock.shared());
+
+        monitor.handle_input(InputKind::Key);
+        monitor.pulse();
+        clock.advance(Duration::from_secs(20));
+        assert!(monitor.check_idle().is_some());
+
+        // not idle anymore as far as anyone asking knows, but resuming
+        // waits for the pulse
+        clock.advance(Duration::from_secs(5));
+        monitor.handle_input(InputKind::MouseMove);
+        assert_eq!(monitor.check_idle(), None);
+        assert_eq!(
+            monitor
+                .get_state()
+                .unwrap()
+                .time_since_last_activity(clock.now()),
+            Some(Duration::ZERO)
+        );
+        monitor.pulse();
+        assert!(!monitor.get_state().unwrap().is_idle);
+
+        let mut events = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            events.push(event);
+        }
+        assert!(matches!(
+            events[..],
+            [
+                ActivityEvent::ActivityPulse { keys: 1, .. },
+                ActivityEvent::IdleDetected { .. },
+                ActivityEvent::ActivityResumed,
+                ActivityEvent::ActivityPulse { mouse_moves: 1, .. },
+            ]
+        ));
+    }
+
+    /// what every single input costs the thread reading it. run with
+    /// `cargo test --release input_overhead -- --ignored --nocapture`
+    #[test]
+    #[ignore = "benchmark"]
+    fn input_overhead_per_event() {
+        let (monitor, _receiver) = InputMonitor::new(&InputConfig::default());
+        let events = 10_000_000;
+
+        let started = std::time::Instant::now();
+        for i in 0..events {
+            if i % 4 == 0 {
+                monitor.handle_input(InputKind::Key);
+            } else {
+                monitor.handle_input(InputKind::MouseMove);
+            }
+        }
+        let elapsed = started.elapsed();
+        println!(
+            "{events} inputs in {elapsed:?}, {:.1}ns per input",
+            elapsed.as_nanos() as f64 / events as f64
+        );
+
+        let Some(ActivityEvent::ActivityPulse {
+            keys, mouse_moves, ..
+        }) = monitor.pulse()
+        else {
+            panic!("no pulse after {events} inputs");
+        };
+        assert_eq!(keys + mouse_moves, events);
+    }
+
+    #[tokio::test]
+    async fn files_tell_when_the_user_left_without_an_input_source() {
+        let clock = MockClock::new();
+        let (monitor, receiver) = InputMonitor::new(&InputConfig::default());
+        let monitor = Arc::new(monitor.with_clock(clock.shared()));
+        let (events_tx, events_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (tracking_tx, mut tracking_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(monitor.clone().receive_events(receiver, events_tx.clone()));
+        tokio::spawn(monitor.clone().count_file_activity(events_rx, tracking_tx));
+        let modified = || TrackingEvents::FileModified {
+            path: "/work/main.rs".to_string(),
+            language: None,
+            project: None,
+        };
+
+        events_tx.send(modified()).unwrap();
+        assert!(matches!(
+            tracking_rx.recv().await,
+            Some(TrackingEvents::FileModified { .. })
+        ));
+        // opens don't count
+        clock.advance(Duration::from_secs(15));
+        events_tx
+            .send(TrackingEvents::FileOpened {
+                path: "/work/lib.rs".to_string(),
+                language: "Rust".to_string(),
+            })
+            .unwrap();
+        assert!(matches!(
+            tracking_rx.recv().await,
+            Some(TrackingEvents::FileOpened { .. })
+        ));
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(monitor.check_idle(), Some(Duration::from_secs(20)));
+        assert!(matches!(
+            tracking_rx.recv().await,
+            Some(TrackingEvents::UserIdle { duration }) if duration == Duration::from_secs(20)
+        ));
+
+        // the next save is the user coming back
+        clock.advance(Duration::from_secs(60));
+        events_tx.send(modified()).unwrap();
+        assert!(matches!(
+            tracking_rx.recv().await,
+            Some(TrackingEvents::FileModified { .. })
+        ));
+        assert!(matches!(
+            tracking_rx.recv().await,
+            Some(TrackingEvents::UserActive)
+        ));
+    }
+
+    #[test]
+    fn no_activity_yet_is_not_idle() {
+        let clock = MockClock::new();
+        let (monitor, _receiver) = InputMonitor::new(&InputConfig::default());
+        let monitor = monitor.with_clock(clock.shared());
+
+        clock.advance(Duration::from_secs(3600));
+        assert_eq!(monitor.check_idle(), None);
     }
 }
@@ -0,0 +1,415 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::stats::TimeEntry;
+
+/// how often the journal is fsync'd to disk. lines are flushed to the
+/// os on every write so only a system crash can lose this much
+const SYNC_INTERVAL: Duration = Duration::from_secs(5);
+
+pub type SharedJournal = Arc<Mutex<Journal>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeartbeatKind {
+    /// the file was opened, modified or focused
+    File,
+    /// there was keyboard or mouse activity while the file was active
+    Input,
+    /// the session of the file stopped running at `time`. what ran up to
+    /// then may still be on its way to the database
+    Paused,
+    /// everything recorded for this file up to `time` was saved as a TimeEntry
+    Saved,
+}
+
+/// a single line of the journal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Heartbeat {
+    pub kind: HeartbeatKind,
+    pub path: PathBuf,
+    pub language: String,
+    pub project: Option<String>,
+    /// milliseconds since unix epoch
+    pub time: u64,
+}
+
+impl Heartbeat {
+    pub fn new(
+        kind: HeartbeatKind,
+        path: PathBuf,
+        language: String,
+        project: Option<String>,
+        time: SystemTime,
+    ) -> Self {
+        Self {
+            kind,
+            path,
+            language,
+            project,
+            time: to_millis(time),
+        }
+    }
+}
+
+/// append-only journal of heartbeats, one json object per line.
+/// if the process dies in the middle of a session, replaying the journal
+/// on the next start gives back the time that was never saved
+#[derive(Debug)]
+pub struct Journal {
+    writer: BufWriter<File>,
+    last_sync: Instant,
+}
+
+impl Journal {
+    /// location of the journal inside the given data directory
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("heartbeats.jsonl")
+    }
+
+    /// creates a new empty journal at the given path, replacing the old one.
+    /// this must only be called after the old journal has been recovered
+    pub fn create(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)
+            .with_context(|| format!("failed to create journal at {}", path.display()))?;
+
+        Ok(Self {
+            writer: BufWriter::new(file),
+            last_sync: Instant::now(),
+        })
+    }
+
+    /// wraps the journal so that it can be written from several tasks
+    pub fn shared(self) -> SharedJournal {
+        Arc::new(Mutex::new(self))
+    }
+
+    /// appends a heartbeat to the journal. the line is handed to the os
+    /// right away and fsync'd once every SYNC_INTERVAL
+    pub fn append(&mut self, heartbeat: &Heartbeat) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, heartbeat)
+            .context("failed to serialize heartbeat")?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush().context("failed to write heartbeat")?;
+
+        if self.last_sync.elapsed() >= SYNC_INTERVAL {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// forces everything written so far onto the disk
+    pub fn sync(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        self.writer
+            .get_ref()
+            .sync_data()
+            .context("failed to sync journal")?;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// replays the journal at the given path and returns a TimeEntry for
+    /// every file whose latest heartbeats were never saved. a missing journal
+    /// and a half written last line (the process died mid write) are not errors
+    pub fn recover(path: &Path) -> Result<Vec<TimeEntry>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to open journal at {}", path.display()));
+            }
+        };
+
+        let mut heartbeats = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.context("failed to read journal")?;
+            if let Ok(heartbeat) = serde_json::from_str::<Heartbeat>(&line) {
+                heartbeats.push(heartbeat);
+            }
+        }
+        Ok(replay(heartbeats))
+    }
+}
+
+/// rebuilds the unsaved part of every session. for each file, everything up
+/// to its latest Saved heartbeat is already in the database. the heartbeats
+/// after that make up spans from the session start to the last sign of
+/// activity, or to the Paused heartbeat that ended them
+fn replay(heartbeats: Vec<Heartbeat>) -> Vec<TimeEntry> {
+    let mut saved_until: HashMap<PathBuf, u64> = HashMap::new();
+    for heartbeat in &heartbeats {
+        if heartbeat.kind == HeartbeatKind::Saved {
+            let saved = saved_until.entry(heartbeat.path.clone()).or_default();
+            *saved = (*saved).max(heartbeat.time);
+        }
+    }
+
+    // (first, last) unsaved heartbeat of the running span of each file
+    let mut running: HashMap<PathBuf, (Heartbeat, u64)> = HashMap::new();
+    let mut spans = Vec::new();
+    for heartbeat in heartbeats {
+        if heartbeat.kind == HeartbeatKind::Saved
+            || saved_until
+                .get(&heartbeat.path)
+                .is_some_and(|saved| heartbeat.time <= *saved)
+        {
+            continue;
+        }
+        if heartbeat.kind == HeartbeatKind::Paused {
+            // an idle pause ends at the last input, which may be before
+            // the heartbeat of a later file event
+            if let Some((first, _)) = running.remove(&heartbeat.path) {
+                let end = heartbeat.time.max(first.time);
+                spans.push((first, end));
+            }
+            continue;
+        }
+        match running.get_mut(&heartbeat.path) {
+            Some((first, last)) => {
+                *last = (*last).max(heartbeat.time);
+                if heartbeat.time < first.time {
+                    *first = heartbeat;
+                }
+            }
+            None => {
+                let time = heartbeat.time;
+                running.insert(heartbeat.path.clone(), (heartbeat, time));
+            }
+        }
+    }
+    spans.extend(running.into_values());
+
+    let mut entries: Vec<TimeEntry> = spans
+        .into_iter()
+        .filter(|(first, last)| *last > first.time)
+        .map(|(first, last)| TimeEntry {
+            duration: Duration::from_millis(last - first.time),
+            start_time: from_millis(first.time),
+            end_time: from_millis(last),
+            path: first.path,
+            language: first.language,
+            project: first.project,
+        })
+        .collect();
+    entries.sort_by_key(|entry| entry.start_time);
+    entries
+}
+
+fn to_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn from_millis(millis: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a journal path no other test uses, removed first
+    fn temp_journal(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("timeit-journal-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        Journal::path_in(&dir)
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_700_000_000 + secs)
+    }
+
+    fn heartbeat(kind: HeartbeatKind, path: &str, secs: u64) -> Heartbeat {
+        Heartbeat::new(
+            kind,
+            PathBuf::from(path),
+            "Rust".to_string(),
+            None,
+            at(secs),
+        )
+    }
+
+    /// writes the heartbeats like a running watcher would and recovers them
+    fn recover(name: &str, heartbeats: &[Heartbeat]) -> Vec<TimeEntry> {
+        let path = temp_journal(name);
+        let mut journal = Journal::create(&path).unwrap();
+        for heartbeat in heartbeats {
+            journal.append(heartbeat).unwrap();
+        }
+        drop(journal);
+        Journal::recover(&path).unwrap()
+    }
+
+    /// (path, start, end) of every entry, times in seconds after at(0)
+    fn spans(entries: &[TimeEntry]) -> Vec<(String, u64, u64)> {
+        let secs = |time: SystemTime| time.duration_since(at(0)).unwrap().as_secs();
+        entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.path.to_string_lossy().to_string(),
+                    secs(entry.start_time),
+                    secs(entry.end_time),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn missing_journal_recovers_nothing() {
+        let path = temp_journal("missing");
+        assert!(Journal::recover(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn unsaved_session_is_recovered() {
+        use HeartbeatKind::*;
+        let entries = recover(
+            "unsaved",
+            &[
+                heartbeat(File, "/app/a.rs", 0),
+                heartbeat(Input, "/app/a.rs", 10),
+                heartbeat(Input, "/app/a.rs", 25),
+            ],
+        );
+        assert_eq!(spans(&entries), [("/app/a.rs".to_string(), 0, 25)]);
+        assert_eq!(entries[0].duration, Duration::from_secs(25));
+    }
+
+    #[test]
+    fn only_the_time_after_the_last_save_is_recovered() {
+        use HeartbeatKind::*;
+        let entries = recover(
+            "resumed",
+            &[
+                heartbeat(File, "/app/a.rs", 0),
+                heartbeat(Input, "/app/a.rs", 10),
+                heartbeat(Saved, "/app/a.rs", 10),
+                // resumed after a break
+                heartbeat(Input, "/app/a.rs", 60),
+                heartbeat(Input, "/app/a.rs", 75),
+            ],
+        );
+        assert_eq!(spans(&entries), [("/app/a.rs".to_string(), 60, 75)]);
+    }
+
+    #[test]
+    fn heartbeats_up_to_a_save_are_skipped() {
+        use HeartbeatKind::*;
+        let entries = recover(
+            "saved",
+            &[
+                heartbeat(File, "/app/a.rs", 0),
+                heartbeat(Input, "/app/a.rs", 10),
+                // at the very time of the save counts as saved too
+                heartbeat(Input, "/app/a.rs", 20),
+                heartbeat(Saved, "/app/a.rs", 20),
+            ],
+        );
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn interleaved_files_are_recovered_apart() {
+        use HeartbeatKind::*;
+        let entries = recover(
+            "interleaved",
+            &[
+                heartbeat(File, "/app/a.rs", 0),
+                heartbeat(File, "/app/b.rs", 5),
+                heartbeat(Input, "/app/a.rs", 8),
+                heartbeat(Saved, "/app/a.rs", 8),
+                heartbeat(Input, "/app/b.rs", 12),
+                heartbeat(File, "/app/c.rs", 20),
+                heartbeat(Input, "/app/c.rs", 30),
+            ],
+        );
+        assert_eq!(
+            spans(&entries),
+            [
+                ("/app/b.rs".to_string(), 5, 12),
+                ("/app/c.rs".to_string(), 20, 30)
+            ]
+        );
+    }
+
+    #[test]
+    fn time_between_a_pause_and_the_next_resume_is_not_recovered() {
+        use HeartbeatKind::*;
+        let entries = recover(
+            "paused",
+            &[
+                heartbeat(File, "/app/a.rs", 0),
+                heartbeat(Input, "/app/a.rs", 10),
+                // idle since the last input, an empty interval that was
+                // never saved
+                heartbeat(Paused, "/app/a.rs", 10),
+                heartbeat(Input, "/app/a.rs", 3600),
+                heartbeat(Input, "/app/a.rs", 3620),
+            ],
+        );
+        assert_eq!(
+            spans(&entries),
+            [
+                ("/app/a.rs".to_string(), 0, 10),
+                ("/app/a.rs".to_string(), 3600, 3620)
+            ]
+        );
+    }
+
+    #[test]
+    fn paused_but_unsaved_time_is_recovered() {
+        use HeartbeatKind::*;
+        let entries = recover(
+            "paused-unsaved",
+            &[
+                heartbeat(File, "/app/a.rs", 0),
+                heartbeat(Input, "/app/a.rs", 5),
+                heartbeat(Paused, "/app/a.rs", 12),
+                heartbeat(File, "/app/b.rs", 12),
+                // a.rs was saved, the crash came before b.rs was
+                heartbeat(Saved, "/app/a.rs", 12),
+                heartbeat(Paused, "/app/b.rs", 20),
+            ],
+        );
+        assert_eq!(spans(&entries), [("/app/b.rs".to_string(), 12, 20)]);
+    }
+
+    #[test]
+    fn half_written_last_line_is_ignored() {
+        use HeartbeatKind::*;
+        let path = temp_journal("truncated");
+        let mut journal = Journal::create(&path).unwrap();
+        journal.append(&heartbeat(File, "/app/a.rs", 0)).unwrap();
+        journal.append(&heartbeat(Input, "/app/a.rs", 10)).unwrap();
+        drop(journal);
+        // the process died in the middle of the next line
+        let line = serde_json::to_string(&heartbeat(Input, "/app/a.rs", 99)).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&line.as_bytes()[..line.len() / 2]).unwrap();
+        drop(file);
+
+        let entries = Journal::recover(&path).unwrap();
+        assert_eq!(spans(&entries), [("/app/a.rs".to_string(), 0, 10)]);
+    }
+}
@@ -0,0 +1,534 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    path::Path,
+};
+
+/// every language that can be detected without any configuration.
+/// languages added through the config are `Other`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Language {
+    Rust,
+    Python,
+    JavaScript,
+    TypeScript,
+    JavaScriptReact,
+    TypeScriptReact,
+    Go,
+    Cpp,
+    C,
+    CHeader,
+    Java,
+    Kotlin,
+    Php,
+    Ruby,
+    Swift,
+    Scala,
+    Clojure,
+    Haskell,
+    Elm,
+    Dart,
+    Lua,
+    VimScript,
+    Bash,
+    Zsh,
+    Fish,
+    PowerShell,
+    Sql,
+    Html,
+    Css,
+    Scss,
+    Sass,
+    Less,
+    Markdown,
+    Yaml,
+    Toml,
+    Json,
+    Xml,
+    Docker,
+    Makefile,
+    CMake,
+    Perl,
+    Prolog,
+    ObjectiveC,
+    Matlab,
+    /// a file without an extension that isn't known by name
+    Text,
+    /// a file with an extension that isn't known
+    Unknown,
+    Other(String),
+}
+
+/// builtin languages with their extensions (without the dot)
+const BUILTIN_EXTENSIONS: &[(Language, &[&str])] = &[
+    (Language::Rust, &["rs"]),
+    (Language::Python, &["py"]),
+    (Language::JavaScript, &["js"]),
+    (Language::TypeScript, &["ts"]),
+    (Language::JavaScriptReact, &["jsx"]),
+    (Language::TypeScriptReact, &["tsx"]),
+    (Language::Go, &["go"]),
+    (Language::Cpp, &["cpp", "cc", "cxx"]),
+    (Language::C, &["c"]),
+    (Language::CHeader, &["h", "hpp"]),
+    (Language::Java, &["java"]),
+    (Language::Kotlin, &["kt"]),
+    (Language::Php, &["php"]),
+    (Language::Ruby, &["rb"]),
+    (Language::Swift, &["swift"]),
+    (Language::Scala, &["scala"]),
+    (Language::Clojure, &["clj"]),
+    (Language::Haskell, &["hs"]),
+    (Language::Elm, &["elm"]),
+    (Language::Dart, &["dart"]),
+    (Language::Lua, &["lua"]),
+    (Language::VimScript, &["vim"]),
+    (Language::Bash, &["sh", "bash"]),
+    (Language::Zsh, &["zsh"]),
+    (Language::Fish, &["fish"]),
+    (Language::PowerShell, &["ps1"]),
+    (Language::Sql, &["sql"]),
+    (Language::Html, &["html"]),
+    (Language::Css, &["css"]),
+    (Language::Scss, &["scss"]),
+    (Language::Sass, &["sass"]),
+    (Language::Less, &["less"]),
+    (Language::Markdown, &["md"]),
+    (Language::Yaml, &["yml", "yaml"]),
+    (Language::Toml, &["toml"]),
+    (Language::Json, &["json"]),
+    (Language::Xml, &["xml"]),
+    // m and pl are ambiguous, the content of the file decides
+    (Language::ObjectiveC, &["m", "mm"]),
+    (Language::Perl, &["pl", "pm"]),
+    (Language::Prolog, &["pro"]),
+];
+
+/// extensions that several languages use
+const AMBIGUOUS_EXTENSIONS: &[&str] = &["h", "m", "pl"];
+
+/// builtin languages of files that are known by their name
+const BUILTIN_FILENAMES: &[(Language, &[&str])] = &[
+    (Language::Docker, &["Dockerfile"]),
+    (Language::Makefile, &["Makefile"]),
+    (Language::CMake, &["CMakeLists.txt"]),
+];
+
+/// languages that are tracked unless the config says otherwise
+const DEFAULT_TRACKED: &[Language] = &[
+    Language::Rust,
+    Language::Python,
+    Language::JavaScript,
+    Language::TypeScript,
+    Language::JavaScriptReact,
+    Language::TypeScriptReact,
+    Language::Go,
+    Language::Cpp,
+    Language::C,
+    Language::CHeader,
+    Language::Java,
+    Language::Kotlin,
+    Language::Php,
+    Language::Ruby,
+    Language::Swift,
+    Language::Scala,
+    Language::Clojure,
+    Language::Haskell,
+    Language::Elm,
+    Language::Dart,
+    Language::Lua,
+    Language::VimScript,
+    Language::Bash,
+    Language::Zsh,
+    Language::Fish,
+    Language::PowerShell,
+    Language::Sql,
+    Language::Perl,
+    Language::Prolog,
+    Language::ObjectiveC,
+    Language::Matlab,
+];
+
+/// other names a language goes by in shebangs and editor modelines
+const ALIASES: &[(&str, Language)] = &[
+    ("python", Language::Python),
+    ("py", Language::Python),
+    ("pypy", Language::Python),
+    ("rs", Language::Rust),
+    ("node", Language::JavaScript),
+    ("nodejs", Language::JavaScript),
+    ("js", Language::JavaScript),
+    ("bun", Language::JavaScript),
+    ("deno", Language::TypeScript),
+    ("ts-node", Language::TypeScript),
+    ("tsx", Language::TypeScriptReact),
+    ("jsx", Language::JavaScriptReact),
+    ("golang", Language::Go),
+    ("c++", Language::Cpp),
+    ("cpp", Language::Cpp),
+    ("sh", Language::Bash),
+    ("dash", Language::Bash),
+    ("ksh", Language::Bash),
+    ("shell-script", Language::Bash),
+    ("php-cli", Language::Php),
+    ("luajit", Language::Lua),
+    ("vim", Language::VimScript),
+    ("pwsh", Language::PowerShell),
+    ("ps1", Language::PowerShell),
+    ("runghc", Language::Haskell),
+    ("runhaskell", Language::Haskell),
+    ("make", Language::Makefile),
+    ("objc", Language::ObjectiveC),
+    ("objective-c", Language::ObjectiveC),
+    ("octave", Language::Matlab),
+    ("swipl", Language::Prolog),
+    ("cperl", Language::Perl),
+    ("yml", Language::Yaml),
+    ("md", Language::Markdown),
+    ("dockerfile", Language::Docker),
+];
+
+impl Language {
+    /// the name shown to users and saved with time entries
+    pub fn name(&self) -> &str {
+        match self {
+            Language::Rust => "Rust",
+            Language::Python => "Python",
+            Language::JavaScript => "JavaScript",
+            Language::TypeScript => "TypeScript",
+            Language::JavaScriptReact => "JavaScript React",
+            Language::TypeScriptReact => "TypeScript React",
+            Language::Go => "Go",
+            Language::Cpp => "C++",
+            Language::C => "C",
+            Language::CHeader => "C/C++ Header",
+            Language::Java => "Java",
+            Language::Kotlin => "Kotlin",
+            Language::Php => "PHP",
+            Language::Ruby => "Ruby",
+            Language::Swift => "Swift",
+            Language::Scala => "Scala",
+            Language::Clojure => "Clojure",
+            Language::Haskell => "Haskell",
+            Language::Elm => "Elm",
+            Language::Dart => "Dart",
+            Language::Lua => "Lua",
+            Language::VimScript => "Vim Script",
+            Language::Bash => "Bash",
+            Language::Zsh => "Zsh",
+            Language::Fish => "Fish",
+            Language::PowerShell => "PowerShell",
+            Language::Sql => "SQL",
+            Language::Html => "HTML",
+            Language::Css => "CSS",
+            Language::Scss => "SCSS",
+            Language::Sass => "Sass",
+            Language::Less => "Less",
+            Language::Markdown => "Markdown",
+            Language::Yaml => "YAML",
+            Language::Toml => "TOML",
+            Language::Json => "JSON",
+            Language::Xml => "XML",
+            Language::Docker => "Docker",
+            Language::Makefile => "Makefile",
+            Language::CMake => "CMake",
+            Language::Perl => "Perl",
+            Language::Prolog => "Prolog",
+            Language::ObjectiveC => "Objective-C",
+            Language::Matlab => "MATLAB",
+            Language::Text => "Text",
+            Language::Unknown => "Unknown",
+            Language::Other(name) => name,
+        }
+    }
+
+    /// finds the language with the given name, ignoring case.
+    /// names that aren't builtin become `Other`
+    pub fn from_name(name: &str) -> Self {
+        BUILTIN_EXTENSIONS
+            .iter()
+            .chain(BUILTIN_FILENAMES)
+            .map(|(language, _)| language)
+            .chain([&Language::Text, &Language::Unknown, &Language::Matlab])
+            .find(|language| language.name().eq_ignore_ascii_case(name))
+            .cloned()
+            .unwrap_or_else(|| Language::Other(name.to_string()))
+    }
+
+    /// finds a builtin language by one of its names or aliases, ignoring case.
+    /// used for the names found in shebangs and modelines
+    pub fn from_alias(alias: &str) -> Option<Self> {
+        let alias = alias.to_ascii_lowercase();
+        if let Some((_, language)) = ALIASES.iter().find(|(name, _)| *name == alias) {
+            return Some(language.clone());
+        }
+        match Language::from_name(&alias) {
+            Language::Other(_) => None,
+            language => Some(language),
+        }
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// language settings of the config. languages are referred to by name
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LanguageConfig {
+    /// files of these languages are tracked, everything else is ignored
+    pub tracked: Vec<String>,
+    /// extra extensions (without the dot), these take precedence over the builtin ones
+    pub extensions: BTreeMap<String, String>,
+    /// extra file names, these take precedence over the builtin ones
+    pub filenames: BTreeMap<String, String>,
+}
+
+impl Default for LanguageConfig {
+    fn default() -> Self {
+        Self {
+            tracked: DEFAULT_TRACKED
+                .iter()
+                .map(|language| language.name().to_string())
+                .collect(),
+            extensions: BTreeMap::new(),
+            filenames: BTreeMap::new(),
+        }
+    }
+}
+
+impl LanguageConfig {
+    /// every extension must be given without its dot and every tracked
+    /// language must be either builtin or added by this config
+    pub fn validate(&self) -> Result<()> {
+        for extension in self.extensions.keys() {
+            if extension.is_empty() || extension.starts_with('.') {
+                bail!("languages.extensions: {extension:?} must be given without the dot");
+            }
+        }
+        let added: HashSet<&str> = self
+            .extensions
+            .values()
+            .chain(self.filenames.values())
+            .map(String::as_str)
+            .collect();
+        for name in &self.tracked {
+            if matches!(Language::from_name(name), Language::Other(_))
+                && !added.contains(name.as_str())
+            {
+                bail!(
+                    "languages.tracked: unknown language {name:?}, add its extensions to [languages.extensions] first"
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// single source of truth for which file is written in which language
+/// and which languages are tracked
+#[derive(Debug, Clone)]
+pub struct LanguageRegistry {
+    extensions: HashMap<String, Language>,
+    filenames: HashMap<String, Language>,
+    tracked: HashSet<Language>,
+}
+
+impl Default for LanguageRegistry {
+    fn default() -> Self {
+        Self::new(&LanguageConfig::default())
+    }
+}
+
+impl LanguageRegistry {
+    /// builtin languages extended by the ones of the config
+    pub fn new(config: &LanguageConfig) -> Self {
+        let mut extensions = HashMap::new();
+        for (language, exts) in BUILTIN_EXTENSIONS {
+            for ext in *exts {
+                extensions.insert(ext.to_string(), language.clone());
+            }
+        }
+        for (ext, name) in &config.extensions {
+            extensions.insert(ext.clone(), Language::from_name(name));
+        }
+
+        let mut filenames = HashMap::new();
+        for (language, names) in BUILTIN_FILENAMES {
+            for name in *names {
+                filenames.insert(name.to_string(), language.clone());
+            }
+        }
+        for (filename, name) in &config.filenames {
+            filenames.insert(filename.clone(), Language::from_name(name));
+        }
+
+        Self {
+            extensions,
+            filenames,
+            tracked: config
+                .tracked
+                .iter()
+                .map(|name| Language::from_name(name))
+                .collect(),
+        }
+    }
+
+    /// detects the language of a file from its name, then its extension
+    pub fn detect(&self, path: &Path) -> Language {
+        if let Some(language) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| self.filenames.get(name))
+        {
+            return language.clone();
+        }
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) => self
+                .extensions
+                .get(ext)
+                .cloned()
+                .unwrap_or(Language::Unknown),
+            None => Language::Text,
+        }
+    }
+
+    /// checks whether the file's extension is shared by several languages,
+    /// or it has no extension at all, so its content has to be looked at
+    pub fn is_ambiguous(&self, path: &Path) -> bool {
+        match self.detect(path) {
+            Language::Text => true,
+            _ => path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| AMBIGUOUS_EXTENSIONS.contains(&ext)),
+        }
+    }
+
+    pub fn is_tracked(&self, language: &Language) -> bool {
+        self.tracked.contains(language)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(extensions: &[(&str, &str)], filenames: &[(&str, &str)]) -> LanguageConfig {
+        let map = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(key, name)| (key.to_string(), name.to_string()))
+                .collect()
+        };
+        LanguageConfig {
+            extensions: map(extensions),
+            filenames: map(filenames),
+            ..LanguageConfig::default()
+        }
+    }
+
+    #[test]
+    fn builtin_languages_are_detected() {
+        let registry = LanguageRegistry::default();
+        let cases = [
+            ("src/main.rs", Language::Rust),
+            ("lib/util.hpp", Language::CHeader),
+            ("build/Makefile", Language::Makefile),
+            // the name wins over the extension
+            ("CMakeLists.txt", Language::CMake),
+            ("notes.txt", Language::Unknown),
+            ("scripts/deploy", Language::Text),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(registry.detect(Path::new(path)), expected, "{path}");
+        }
+    }
+
+    #[test]
+    fn only_tracked_languages_are_tracked() {
+        let registry = LanguageRegistry::default();
+        assert!(registry.is_tracked(&Language::Rust));
+        assert!(registry.is_tracked(&Language::Matlab));
+        assert!(!registry.is_tracked(&Language::Markdown));
+        assert!(!registry.is_tracked(&Language::Unknown));
+
+        let registry = LanguageRegistry::new(&LanguageConfig {
+            tracked: vec!["markdown".to_string()],
+            ..LanguageConfig::default()
+        });
+        assert!(registry.is_tracked(&Language::Markdown));
+        assert!(!registry.is_tracked(&Language::Rust));
+    }
+
+    #[test]
+    fn the_config_takes_precedence_over_the_builtins() {
+        let registry = LanguageRegistry::new(&config(
+            &[("h", "C"), ("j2", "Jinja")],
+            &[("Makefile", "Bash"), ("Justfile", "Just")],
+        ));
+        let cases = [
+            ("include/lib.h", Language::C),
+            ("templates/page.j2", Language::Other("Jinja".to_string())),
+            ("Makefile", Language::Bash),
+            ("Justfile", Language::Other("Just".to_string())),
+            // untouched builtins are still there
+            ("src/main.rs", Language::Rust),
+            ("Dockerfile", Language::Docker),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(registry.detect(Path::new(path)), expected, "{path}");
+        }
+    }
+
+    #[test]
+    fn languages_of_the_config_are_validated() {
+        let tracking = |config: LanguageConfig, names: &[&str]| LanguageConfig {
+            tracked: names.iter().map(|name| name.to_string()).collect(),
+            ..config
+        };
+        let valid = [
+            ("defaults", LanguageConfig::default()),
+            ("builtin", tracking(config(&[], &[]), &["Rust", "markdown"])),
+            (
+                "added extension",
+                tracking(config(&[("j2", "Jinja")], &[]), &["Jinja"]),
+            ),
+            (
+                "added filename",
+                tracking(config(&[], &[("Justfile", "Just")]), &["Just"]),
+            ),
+        ];
+        for (case, config) in valid {
+            assert!(config.validate().is_ok(), "{case}");
+        }
+
+        let invalid = [
+            (
+                "empty extension",
+                config(&[("", "Rust")], &[]),
+                "without the dot",
+            ),
+            (
+                "dotted extension",
+                config(&[(".rs", "Rust")], &[]),
+                "without the dot",
+            ),
+            (
+                "unknown language",
+                tracking(config(&[], &[]), &["Jinja"]),
+                "unknown language",
+            ),
+        ];
+        for (case, config, message) in invalid {
+            let error = config.validate().unwrap_err().to_string();
+            assert!(error.contains(message), "{case}: {error}");
+        }
+    }
+}
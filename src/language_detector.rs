@@ -0,0 +1,388 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use crate::language::{Language, LanguageRegistry};
+
+/// how much of the start of a file is read to look for a shebang,
+/// modelines and the markers used by the heuristics
+const HEAD_SIZE: u64 = 8 * 1024;
+/// how much of the end of a file is read to look for vim modelines
+const TAIL_SIZE: u64 = 1024;
+/// vim only looks at this many lines at each end of a file for modelines
+const MODELINE_LINES: usize = 5;
+
+/// detects languages by name and extension first. files that have no
+/// extension or an ambiguous one are read once to look at their shebang,
+/// vim/emacs modelines and some language specific markers. the result is
+/// cached per path so files are not read again on every event
+#[derive(Debug)]
+pub struct LanguageDetector {
+    registry: LanguageRegistry,
+    cache: HashMap<PathBuf, Language>,
+}
+
+impl LanguageDetector {
+    pub fn new(registry: LanguageRegistry) -> Self {
+        Self {
+            registry,
+            cache: HashMap::new(),
+        }
+    }
+
+    pub fn registry(&self) -> &LanguageRegistry {
+        &self.registry
+    }
+
+    /// returns the language of the file at path
+    pub fn detect(&mut self, path: &Path) -> Language {
+        if !self.registry.is_ambiguous(path) {
+            return self.registry.detect(path);
+        }
+        if let Some(language) = self.cache.get(path) {
+            return language.clone();
+        }
+
+        let fallback = self.registry.detect(path);
+        // a new file is often created empty and written right after, so
+        // there is nothing to look at yet. opening it would also show up
+        // as one more event for the file watcher
+        if std::fs::metadata(path).is_ok_and(|metadata| metadata.len() == 0) {
+            return fallback;
+        }
+        let Some(content) = read_head_and_tail(path) else {
+            // unreadable (or already gone), try again next time
+            return fallback;
+        };
+        let language = detect_from_content(path, &content).unwrap_or(fallback);
+        self.cache.insert(path.to_path_buf(), language.clone());
+        language
+    }
+
+    /// drops the cached language of a path, e.g. after it was removed or renamed
+    pub fn forget(&mut self, path: &Path) {
+        self.cache.remove(path);
+    }
+}
+
+/// modelines are the most explicit, then the shebang, then the heuristics
+/// of the file's extension
+fn detect_from_content(path: &Path, content: &str) -> Option<Language> {
+    if let Some(language) = detect_modeline(content) {
+        return Some(language);
+    }
+    if let Some(language) = content.lines().next().and_then(detect_shebang) {
+        return Some(language);
+    }
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("h") => Some(detect_header(content)),
+        Some("m") => Some(detect_m(content)),
+        Some("pl") => Some(detect_pl(content)),
+        _ => None,
+    }
+}
+
+/// reads the start of a file and, for bigger files, its end as well.
+/// binary files are skipped
+fn read_head_and_tail(path: &Path) -> Option<String> {
+    let mut file = File::open(path).ok()?;
+    if !file.metadata().ok()?.is_file() {
+        return None;
+    }
+
+    let mut buffer = Vec::new();
+    file.by_ref()
+        .take(HEAD_SIZE)
+        .read_to_end(&mut buffer)
+        .ok()?;
+    let size = file.metadata().ok()?.len();
+    if size > HEAD_SIZE {
+        file.seek(SeekFrom::Start(
+            size.saturating_sub(TAIL_SIZE).max(HEAD_SIZE),
+        ))
+        .ok()?;
+        buffer.push(b'\n');
+        file.read_to_end(&mut buffer).ok()?;
+    }
+
+    if buffer.contains(&0) {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+/// `#!/usr/bin/python3`, `#!/usr/bin/env python3` or `#!/usr/bin/env -S node --flag`
+fn detect_shebang(line: &str) -> Option<Language> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let mut interpreter = words.next()?.rsplit('/').next()?;
+    if interpreter == "env" {
+        interpreter = words.find(|word| !word.starts_with('-'))?;
+    }
+
+    // python3.12 -> python
+    let name = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    match name {
+        "cargo" | "rust-script" => Some(Language::Rust),
+        _ => Language::from_alias(name),
+    }
+}
+
+/// vim modelines (`vim: set ft=python:`, `vi: filetype=sh`) in the first or
+/// last lines, or an emacs mode line (`-*- mode: python -*-`, `-*- python -*-`)
+/// in the first two lines
+fn detect_modeline(content: &str) -> Option<Language> {
+    let lines: Vec<&str> = content.lines().collect();
+    let head = lines.iter().take(MODELINE_LINES);
+    let tail = lines.iter().skip(
+        lines
+            .len()
+            .saturating_sub(MODELINE_LINES)
+            .max(MODELINE_LINES),
+    );
+    if let Some(language) = head.chain(tail).find_map(|line| detect_vim_modeline(line)) {
+        return Some(language);
+    }
+    lines
+        .iter()
+        .take(2)
+        .find_map(|line| detect_emacs_modeline(line))
+}
+
+fn detect_vim_modeline(line: &str) -> Option<Language> {
+    let start = ["vim:", "vi:", "ex:"]
+        .iter()
+        .filter_map(|marker| line.find(marker).map(|index| index + marker.len()))
+        .min()?;
+    line[start..]
+        .split(|c: char| c == ':' || c.is_whitespace())
+        .filter_map(|option| option.split_once('='))
+        .find(|(key, _)| matches!(*key, "ft" | "filetype" | "syntax" | "syn"))
+        .and_then(|(_, value)| Language::from_alias(value))
+}
+
+fn detect_emacs_modeline(line: &str) -> Option<Language> {
+    let start = line.find("-*-")? + 3;
+    let end = start + line[start..].find("-*-")?;
+    let settings = line[start..end].trim();
+
+    let mode = if settings.contains(':') {
+        settings
+            .split(';')
+            .filter_map(|setting| setting.split_once(':'))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case("mode"))
+            .map(|(_, value)| value.trim())?
+    } else {
+        settings
+    };
+    let mode = mode.strip_suffix("-mode").unwrap_or(mode);
+    Language::from_alias(mode)
+}
+
+/// .h files are shared by c, c++ and objective-c
+fn detect_header(content: &str) -> Language {
+    if is_objective_c(content) {
+        return Language::ObjectiveC;
+    }
+    let cpp_markers = [
+        "namespace ",
+        "template <",
+        "template<",
+        "std::",
+        "public:",
+        "private:",
+        "protected:",
+        "class ",
+        "constexpr ",
+        "#include <iostream>",
+        "#include <string>",
+        "#include <vector>",
+    ];
+    if cpp_markers.iter().any(|marker| content.contains(marker)) {
+        Language::Cpp
+    } else {
+        Language::C
+    }
+}
+
+/// .m files are shared by objective-c and matlab
+fn detect_m(content: &str) -> Language {
+    if is_objective_c(content) {
+        return Language::ObjectiveC;
+    }
+    let is_matlab = content
+        .lines()
+        .map(str::trim_start)
+        .any(|line| line.starts_with('%') || line.starts_with("function ") || line == "end");
+    if is_matlab {
+        Language::Matlab
+    } else {
+        Language::ObjectiveC
+    }
+}
+
+/// .pl files are shared by perl and prolog
+fn detect_pl(content: &str) -> Language {
+    let is_perl = content.lines().map(str::trim_start).any(|line| {
+        line.starts_with("use strict")
+            || line.starts_with("use warnings")
+            || line.starts_with("my $")
+            || line.starts_with("sub ")
+            || line.starts_with("package ")
+    });
+    if is_perl {
+        return Language::Perl;
+    }
+    let is_prolog = content.lines().any(|line| {
+        let line = line.trim();
+        line.starts_with(":-") || line.ends_with(":-") || line.contains(") :-")
+    });
+    if is_prolog {
+        Language::Prolog
+    } else {
+        Language::Perl
+    }
+}
+
+fn is_objective_c(content: &str) -> bool {
+    content.lines().map(str::trim_start).any(|line| {
+        line.starts_with("@interface")
+            || line.starts_with("@implementation")
+            || line.starts_with("@protocol")
+            || line.starts_with("#import ")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shebangs() {
+        let cases = [
+            ("#!/usr/bin/python3.12", Some(Language::Python)),
+            ("#!/usr/bin/env python3", Some(Language::Python)),
+            ("#!/usr/bin/env -S python3.12 -u", Some(Language::Python)),
+            (
+                "#!/usr/bin/env -S cargo +nightly -Zscript",
+                Some(Language::Rust),
+            ),
+            ("#! /bin/sh -e", Some(Language::Bash)),
+            ("#!/usr/bin/env node", Some(Language::JavaScript)),
+            ("#!/usr/bin/env", None),
+            ("#!/usr/bin/env -S", None),
+            ("#!/usr/bin/not-a-language", None),
+            ("# just a comment", None),
+        ];
+        for (line, expected) in cases {
+            assert_eq!(detect_shebang(line), expected, "{line}");
+        }
+    }
+
+    #[test]
+    fn vim_modelines() {
+        let cases = [
+            ("# vim: set ft=python:", Some(Language::Python)),
+            ("// vi: filetype=sh", Some(Language::Bash)),
+            ("/* vim: set ts=4 syntax=cpp : */", Some(Language::Cpp)),
+            ("ex: ts=2 ft=ruby", Some(Language::Ruby)),
+            ("# vim: ts=4 sw=4", None),
+            ("# vim: ft=nonsense", None),
+            ("no modeline here", None),
+        ];
+        for (line, expected) in cases {
+            assert_eq!(detect_vim_modeline(line), expected, "{line}");
+        }
+    }
+
+    #[test]
+    fn emacs_modelines() {
+        let cases = [
+            ("# -*- mode: python -*-", Some(Language::Python)),
+            ("# -*- Mode: ruby; tab-width: 2 -*-", Some(Language::Ruby)),
+            ("// -*- c++ -*-", Some(Language::Cpp)),
+            ("# -*- sh-mode -*-", Some(Language::Bash)),
+            ("# -*- coding: utf-8 -*-", None),
+            ("# -*- unterminated", None),
+        ];
+        for (line, expected) in cases {
+            assert_eq!(detect_emacs_modeline(line), expected, "{line}");
+        }
+    }
+
+    #[test]
+    fn modelines_are_looked_for_at_both_ends() {
+        let middle = "x = 1\n".repeat(20);
+        let tail = format!("print(1)\n{middle}# vim: ft=python\n");
+        assert_eq!(detect_modeline(&tail), Some(Language::Python));
+        // only at the ends, not somewhere in between
+        let buried = format!("{middle}# vim: ft=python\n{middle}");
+        assert_eq!(detect_modeline(&buried), None);
+        // emacs only looks at the first two lines
+        let late = "\n\n# -*- mode: ruby -*-\n";
+        assert_eq!(detect_modeline(late), None);
+    }
+
+    #[test]
+    fn headers() {
+        let cases = [
+            (
+                "#import <Foundation/Foundation.h>\n@interface Foo\n",
+                Language::ObjectiveC,
+            ),
+            ("namespace app {\nclass Foo;\n}\n", Language::Cpp),
+            ("#include <vector>\n", Language::Cpp),
+            ("#include <stdio.h>\nint main(void);\n", Language::C),
+        ];
+        for (content, expected) in cases {
+            assert_eq!(detect_header(content), expected, "{content}");
+        }
+    }
+
+    #[test]
+    fn m_files() {
+        let cases = [
+            ("@implementation Foo\n@end\n", Language::ObjectiveC),
+            (
+                "% computes y\nfunction y = f(x)\n  y = x;\nend\n",
+                Language::Matlab,
+            ),
+            ("int x;\n", Language::ObjectiveC),
+        ];
+        for (content, expected) in cases {
+            assert_eq!(detect_m(content), expected, "{content}");
+        }
+    }
+
+    #[test]
+    fn pl_files() {
+        let cases = [
+            ("use strict;\nmy $x = 1;\n", Language::Perl),
+            (
+                "parent(tom, bob).\ngrandparent(X, Z) :- parent(X, Y), parent(Y, Z).\n",
+                Language::Prolog,
+            ),
+            (":- initialization(main).\n", Language::Prolog),
+            ("print 1;\n", Language::Perl),
+        ];
+        for (content, expected) in cases {
+            assert_eq!(detect_pl(content), expected, "{content}");
+        }
+    }
+
+    #[test]
+    fn modelines_win_over_shebangs_and_heuristics() {
+        let content = "#!/usr/bin/env perl\n# vim: ft=python\nuse strict;\n";
+        assert_eq!(
+            detect_from_content(Path::new("/work/tool.pl"), content),
+            Some(Language::Python)
+        );
+        let content = "#!/usr/bin/env swipl\nuse strict;\n";
+        assert_eq!(
+            detect_from_content(Path::new("/work/tool.pl"), content),
+            Some(Language::Prolog)
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use tracing_appender::{non_blocking::WorkerGuard, rolling};
+use tracing_subscriber::{EnvFilter, Layer, fmt, layer::SubscriberExt, util::SubscriberInitExt};
+
+use crate::event_log::EventLogLayer;
+
+/// name of the log files in the log directory, the date is appended to it
+const LOG_FILE: &str = "timeit.log";
+
+/// where log events go to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    /// printed to stderr, which keeps stdout for exports and reports
+    Terminal,
+    /// kept for the dashboard, which owns the terminal
+    Dashboard,
+}
+
+/// sets up the global subscriber. filter takes the same directives as
+/// RUST_LOG, e.g. `debug` or `wakatime=trace,warn`. with a log directory,
+/// every event is also written there as json, in a new file every day.
+/// the returned guard flushes that file when dropped
+pub fn init(filter: &str, output: Output, log_dir: Option<&Path>) -> Result<Option<WorkerGuard>> {
+    let filter =
+        EnvFilter::try_new(filter).with_context(|| format!("invalid log filter {filter:?}"))?;
+
+    let terminal = match output {
+        Output::Terminal => fmt::layer()
+            .with_target(false)
+            .with_writer(std::io::stderr)
+            .boxed(),
+        Output::Dashboard => EventLogLayer.boxed(),
+    };
+
+    let (file, guard) = match log_dir {
+        Some(log_dir) => {
+            std::fs::create_dir_all(log_dir)
+                .with_context(|| format!("failed to create {}", log_dir.display()))?;
+            let (writer, guard) = tracing_appender::non_blocking(rolling::daily(log_dir, LOG_FILE));
+            let layer = fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_span_list(false)
+                .with_writer(writer);
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(terminal)
+        .with(file)
+        .try_init()
+        .context("failed to set up logging")?;
+    Ok(guard)
+}
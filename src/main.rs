@@ -1,55 +1,467 @@
+mod activity_validator;
+mod cli;
+mod clock;
+mod commands;
+mod config;
+mod daemon;
+mod editor_protocol;
+mod event_log;
+mod export;
 mod file_session;
 mod file_watcher;
+mod html_report;
+mod idle_source;
+mod ignore_rules;
+mod input_source;
 mod input_watcher;
+mod journal;
+mod language;
+mod language_detector;
+mod logging;
 mod manager;
+mod report;
 mod stats;
+mod storage;
+mod temp_files;
 mod tracking_event;
+mod tui;
+mod wakatime_api;
 
-use crate::input_watcher::InputMonitor;
+use crate::cli::{Cli, Command};
+use crate::clock::{SharedClock, SystemClock};
+use crate::config::{Config, WatcherConfig};
+use crate::daemon::Daemon;
+use crate::export::Filter;
+use crate::idle_source::IdleSource;
+use crate::input_watcher::{InputMonitor, PULSE_INTERVAL};
+use crate::journal::{Heartbeat, HeartbeatKind, Journal};
+use crate::language::LanguageRegistry;
+use crate::logging::Output;
+use crate::manager::{Manager, ManagerCommand};
+use crate::report::Period;
+use crate::stats::{DailyStats, entry_date};
+use crate::storage::Storage;
+use crate::tui::Dashboard;
+use anyhow::{Context, anyhow};
+use chrono::Local;
+use clap::Parser;
 use file_watcher::FileWatcher;
-use notify::{Event, RecursiveMode, Result, Watcher};
-use std::path::Path;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Result, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-
-//WARNING:use a macro for logging and a thread local buffer
-//################################################################
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::{mpsc::UnboundedSender, oneshot};
+use tracing::{error, info, info_span, warn};
+use tracking_event::TrackingEvents;
 
 #[tokio::main]
-async fn main() {
-    println!("Starting filtered file watcher...");
-    println!("Watching current directory for source code changes");
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    // the dashboard shows the log itself, printing it would draw over it
+    let output = match cli.command {
+        Command::Watch { tui: true, .. } => Output::Dashboard,
+        _ => Output::Terminal,
+    };
+    let _log_guard = logging::init(&cli.log_level, output, cli.log_dir.as_deref())?;
+    let data_dir = match cli.data_dir {
+        Some(data_dir) => data_dir,
+        None => storage::default_data_dir()?,
+    };
+    let config_path = match cli.config {
+        Some(config_path) => config_path,
+        None => Config::default_path()?,
+    };
+
+    match cli.command {
+        Command::Watch { dirs, tui } => watch(&data_dir, &config_path, dirs, tui).await,
+        Command::Status => commands::status(&data_dir),
+        Command::Pause => commands::pause(&data_dir),
+        Command::Resume => commands::resume(&data_dir),
+        Command::Reload => commands::reload(&data_dir),
+        Command::Editor => commands::editor(&data_dir),
+        Command::Today => commands::today(&data_dir),
+        Command::Report {
+            day,
+            week,
+            month,
+            from,
+            to,
+            top,
+            html,
+        } => {
+            let today = Local::now().date_naive();
+            let period = match (day, week, month) {
+                (Some(day), _, _) => Period::Day(day),
+                (_, Some(week), _) => Period::Week(week.unwrap_or(today)),
+                (_, _, Some(month)) => Period::Month(month.unwrap_or(today)),
+                _ => Period::Range(from.unwrap_or(today), to.unwrap_or(today)),
+            };
+            commands::report(&data_dir, period, top, html.as_deref())
+        }
+        Command::Export {
+            from,
+            to,
+            format,
+            daily,
+            project,
+            language,
+            output,
+        } => {
+            let filter = Filter {
+                projects: project,
+                languages: language,
+            };
+            commands::export(
+                &data_dir,
+                from,
+                to,
+                filter,
+                format,
+                daily,
+                output.as_deref(),
+            )
+        }
+        Command::Config { init } => commands::config(&data_dir, &config_path, init),
+    }
+}
+
+/// watches the given directories and tracks time until SIGINT or SIGTERM,
+/// or until the dashboard is closed with tui. everything tracked so far is
+/// saved before returning. other processes can control the watcher through
+/// the daemon socket
+async fn watch(
+    data_dir: &Path,
+    config_path: &Path,
+    dirs: Vec<PathBuf>,
+    tui: bool,
+) -> anyhow::Result<()> {
+    info!("Starting filtered file watcher...");
+    let config = Config::load(config_path)?;
+    // absolute paths keep sessions apart when the same file is reached differently
+    let dirs = dirs
+        .iter()
+        .map(|dir| {
+            dir.canonicalize()
+                .with_context(|| format!("failed to find {}", dir.display()))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    // binding first makes sure this is the only watcher using the data directory
+    std::fs::create_dir_all(data_dir)
+        .with_context(|| format!("failed to create {}", data_dir.display()))?;
+    let socket_path = Daemon::socket_path_in(data_dir);
+    let listener = Daemon::bind(&socket_path)?;
+
+    let db_path = Storage::path_in(data_dir);
+    let storage = Storage::open(&db_path)?;
+    info!("Saving tracked time to {}", db_path.display());
+
+    // whatever was left unsaved by a crash is recovered before
+    // the journal gets replaced by a new one
+    let journal_path = Journal::path_in(data_dir);
+    let recovered = Journal::recover(&journal_path)?;
+    for entry in &recovered {
+        storage.insert_entry(entry)?;
+        info!(
+            "recovered {:?} on {} from an interrupted session",
+            entry.duration,
+            entry.path.display()
+        );
+    }
+    let journal = Journal::create(&journal_path)?.shared();
+    // the manager, the input monitor and the file watchers share one clock
+    let clock = SystemClock::shared();
+
+    // every watcher reports to the manager through this channel
+    let (tracking_tx, mut tracking_rx) = tokio::sync::mpsc::unbounded_channel();
+    let (command_tx, command_rx) = tokio::sync::mpsc::unbounded_channel();
+    let (manager, mut entries) = Manager::new();
+    let manager = manager
+        .with_journal(journal.clone())
+        .with_clock(clock.clone())
+        .with_languages(LanguageRegistry::new(&config.languages))
+        .with_commands(command_rx);
+
+    let (reload_tx, mut reload_rx) = tokio::sync::mpsc::unbounded_channel();
+    let daemon = Arc::new(Daemon::new(
+        command_tx.clone(),
+        reload_tx.clone(),
+        Storage::open(&db_path)?,
+    ));
+    tokio::spawn(daemon.serve(listener));
+    info!("Listening for commands on {}", socket_path.display());
+
+    let editor_socket_path = editor_protocol::socket_path_in(data_dir);
+    let editor_listener = daemon::bind_socket(&editor_socket_path)?;
+    tokio::spawn(editor_protocol::serve(editor_listener, tracking_tx.clone()));
+    info!(
+        "Listening for editor events on {}",
+        editor_socket_path.display()
+    );
+
+    if config.api.enabled {
+        let address = config.api.address()?;
+        let api = wakatime_api::bind(address, tracking_tx.clone(), clock.clone()).await?;
+        tokio::spawn(api);
+        info!("Accepting wakatime heartbeats on http://{address}/api/v1");
+    }
+
+    // finished entries are saved and aggregated per day. the stats of today
+    // are rebuilt from the database so the totals survive restarts. the
+    // thread ends once the manager is gone and every entry is saved
+    let saver = std::thread::spawn(move || {
+        let today = Local::now().date_naive();
+        let mut daily_stats: HashMap<String, DailyStats> = storage
+            .daily_stats(today, today)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|stats| (stats.date.clone(), stats))
+            .collect();
+
+        while let Some(entry) = entries.blocking_recv() {
+            match storage.insert_entry(&entry) {
+                Ok(()) => {
+                    // tells the journal that this part of the session is safe
+                    let heartbeat = Heartbeat::new(
+                        HeartbeatKind::Saved,
+                        entry.path.clone(),
+                        entry.language.clone(),
+                        entry.project.clone(),
+                        entry.end_time,
+                    );
+                    let mut journal = journal.lock().expect("failed to get a lock on the journal");
+                    if let Err(e) = journal.append(&heartbeat) {
+                        warn!("failed to write heartbeat: {e:#}");
+                    }
+                }
+                Err(e) => error!("failed to save time entry: {e:#}"),
+            }
+
+            let date = entry_date(entry.start_time);
+            let stats = daily_stats
+                .entry(date.clone())
+                .or_insert_with(|| DailyStats::new(date));
+            stats.add_entry(&entry);
+
+            info!(
+                "tracked {:?} on {} ({}, {}) until {:?}, {} total: {:?}",
+                entry.duration,
+                entry.path.display(),
+                entry.language,
+                entry.project.as_deref().unwrap_or("no project"),
+                entry.end_time,
+                stats.date,
+                stats.total_time
+            );
+        }
+    });
 
     // we create an input monitor and its receiver channel
-    let (input_monitor, receiver) = InputMonitor::new();
+    let (input_monitor, receiver) = InputMonitor::new(&config.input);
     // wrap an arc around it so that we could pass it around in threads
-    let input_monitor = Arc::new(input_monitor);
+    let input_monitor = Arc::new(input_monitor.with_clock(clock.clone()));
 
     // one taks to monitor idle activity
     // one task to receive events
-    tokio::spawn(input_monitor.clone().start_idle_monitoring(20));
-    tokio::spawn(input_monitor.clone().receive_events(receiver));
+    tokio::spawn(
+        input_monitor
+            .clone()
+            .start_idle_monitoring(config.input.idle_check_interval_secs),
+    );
+    tokio::spawn(
+        input_monitor
+            .clone()
+            .receive_events(receiver, tracking_tx.clone()),
+    );
+
+    let mut watchers = spawn_file_watchers(&config, &dirs, &clock, &tracking_tx)?;
+
+    // both the input listener and the file watchers block, they get threads
+    // of their own so that they can't hold up the manager and the daemon.
+    // idle sources ask the desktop instead of seeing every key press. without
+    // either, saves and focus changes count as activity, so that sessions
+    // still end once the files are left alone for the idle threshold
+    let has_activity_source = match IdleSource::open(&config.input).await {
+        Ok(Some(source)) => {
+            info!("Asking {} whether the user is idle", source.name());
+            tokio::spawn(
+                input_monitor
+                    .clone()
+                    .start_idle_source(source, config.input.idle_check_interval_secs),
+            );
+            true
+        }
+        Ok(None) => match input_source::open(config.input.backend) {
+            Ok(source) => {
+                info!(
+                    "Listening for keyboard and mouse input with {}",
+                    source.name()
+                );
+                // a plain thread, the runtime would wait for a blocking task
+                // that never returns when it shuts down
+                let activity_monitor = input_monitor.clone();
+                std::thread::spawn(move || activity_monitor.start_activity_monitoring(source));
+                tokio::spawn(input_monitor.clone().start_pulses(PULSE_INTERVAL));
+                true
+            }
+            Err(e) => {
+                warn!("not listening for keyboard and mouse input: {e:#}");
+                false
+            }
+        },
+        Err(e) => {
+            warn!("not checking whether the user is idle: {e:#}");
+            false
+        }
+    };
+    if !has_activity_source {
+        let (relay_tx, relay_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(
+            input_monitor
+                .clone()
+                .count_file_activity(tracking_rx, relay_tx),
+        );
+        tracking_rx = relay_rx;
+    }
+    // runs once it's known where its events come from
+    let manager = tokio::spawn(manager.run(tracking_rx));
+
+    // the daemon asks for the config to be reloaded. the new watchers are
+    // started before the old ones are dropped so that no change is missed
+    let reload = async {
+        while let Some(reply) = reload_rx.recv().await {
+            let result = Config::load(config_path).and_then(|config| {
+                let new_watchers = spawn_file_watchers(&config, &dirs, &clock, &tracking_tx)?;
+                // the idle check interval only changes on restart
+                input_monitor.set_idle_threshold(config.input.idle_threshold());
+                let languages = LanguageRegistry::new(&config.languages);
+                let _ = command_tx.send(ManagerCommand::SetLanguages(languages));
+                Ok(new_watchers)
+            });
+            let result = match result {
+                Ok(new_watchers) => {
+                    watchers = new_watchers;
+                    info!("reloaded {}", config_path.display());
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            };
+            let _ = reply.send(result);
+        }
+    };
+
+    // SIGINT and SIGTERM stop the watcher, SIGHUP reloads the config like
+    // the reload command does
+    let mut interrupt = signal(SignalKind::interrupt()).context("failed to handle SIGINT")?;
+    let mut terminate = signal(SignalKind::terminate()).context("failed to handle SIGTERM")?;
+    let mut hangup = signal(SignalKind::hangup()).context("failed to handle SIGHUP")?;
+    let signals = async {
+        loop {
+            tokio::select! {
+                _ = interrupt.recv() => return "SIGINT",
+                _ = terminate.recv() => return "SIGTERM",
+                _ = hangup.recv() => {
+                    let (reply, result) = oneshot::channel();
+                    let _ = reload_tx.send(reply);
+                    if let Ok(Err(e)) = result.await {
+                        error!("failed to reload on SIGHUP: {e:#}");
+                    }
+                }
+            }
+        }
+    };
+
+    let result = if tui {
+        let dashboard = Dashboard::new(
+            command_tx.clone(),
+            Storage::open(&db_path)?,
+            input_monitor.clone(),
+            clock.clone(),
+        );
+        tokio::select! {
+            _ = reload => Ok(()),
+            result = dashboard.run() => result,
+            signal = signals => {
+                info!("stopping on {signal}");
+                Ok(())
+            }
+        }
+    } else {
+        println!("Press Ctrl+C to stop\n");
+        tokio::select! {
+            _ = reload => Ok(()),
+            signal = signals => {
+                info!("stopping on {signal}");
+                Ok(())
+            }
+        }
+    };
 
+    // no session may start while the last ones are saved
+    drop(watchers);
+    let _ = command_tx.send(ManagerCommand::Shutdown);
+    manager.await.context("the manager failed")?;
+    // the manager is gone, so the saver gets every entry it sent and stops
+    tokio::task::spawn_blocking(move || saver.join())
+        .await?
+        .map_err(|_| anyhow!("failed to save the last time entries"))?;
+    for path in [&socket_path, &editor_socket_path] {
+        if let Err(e) = std::fs::remove_file(path) {
+            warn!("failed to remove {}: {e}", path.display());
+        }
+    }
+    info!("stopped, all tracked time is saved");
+    result
+}
+
+/// each directory gets its own watcher so that it can use the
+/// settings of its project. watchers stop once they are dropped
+fn spawn_file_watchers(
+    config: &Config,
+    dirs: &[PathBuf],
+    clock: &SharedClock,
+    tracking_tx: &UnboundedSender<TrackingEvents>,
+) -> anyhow::Result<Vec<RecommendedWatcher>> {
+    let mut watchers = Vec::new();
+    for dir in dirs {
+        let project_config = config.for_project(dir)?;
+        watchers.push(spawn_file_watcher(
+            dir,
+            &project_config.watcher,
+            LanguageRegistry::new(&project_config.languages),
+            clock.clone(),
+            tracking_tx.clone(),
+        )?);
+        info!("Watching {} for source code changes", dir.display());
+    }
+    Ok(watchers)
+}
+
+/// starts watching a directory recursively. the raw events are handed to a
+/// FileWatcher task which reports source file changes to the tracking channel
+fn spawn_file_watcher(
+    dir: &Path,
+    config: &WatcherConfig,
+    languages: LanguageRegistry,
+    clock: SharedClock,
+    tracking_tx: UnboundedSender<TrackingEvents>,
+) -> anyhow::Result<RecommendedWatcher> {
     let (tx, rx) = crossbeam::channel::bounded(10);
     let rx = Arc::new(rx);
-    let tx_clone = tx.clone();
     let handler = move |event: Result<Event>| {
-        let _ = tx_clone.send(event);
+        let _ = tx.send(event);
     };
-    let mut watcher = notify::recommended_watcher(handler).unwrap();
-
+    let mut watcher = notify::recommended_watcher(handler)?;
     watcher
-        .watch(Path::new("."), RecursiveMode::Recursive)
-        .expect("failed to watch using watcher!");
+        .watch(dir, RecursiveMode::Recursive)
+        .with_context(|| format!("failed to watch {}", dir.display()))?;
 
-    tokio::spawn(async move {
-        let mut file_watcher = FileWatcher::new();
-        file_watcher.handle_file_watcher(rx.clone()).await;
+    let mut file_watcher = FileWatcher::new(dir, config, languages)?.with_clock(clock);
+    let span = info_span!("file_watcher", dir = %dir.display());
+    tokio::task::spawn_blocking(move || {
+        let _span = span.entered();
+        file_watcher.handle_file_watcher(rx, tracking_tx);
     });
-
-    tokio::spawn(input_monitor.start_activity_monitoring());
-
-    println!("Press Ctrl+C to stop\n");
-    loop {
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-    }
+    Ok(watcher)
 }
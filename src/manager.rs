@@ -1,7 +1,877 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{
+    mpsc::{self, UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
+use tracing::{info, instrument, trace, warn};
+
+use crate::{
+    clock::{SharedClock, SystemClock},
+    file_session::{FileSession, Interval, detect_project},
+    journal::{Heartbeat, HeartbeatKind, SharedJournal},
+    language::LanguageRegistry,
+    stats::TimeEntry,
+    tracking_event::TrackingEvents,
+};
+
+/// input activity is written to the journal at most once per this interval
+const INPUT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// requests other tasks can make to a running manager
+#[derive(Debug)]
+pub enum ManagerCommand {
+    /// replies with what the manager is tracking right now
+    Status(oneshot::Sender<ManagerStatus>),
+    /// stops tracking until resumed. the active session is saved first
+    Pause,
+    Resume,
+    /// files of sessions created from now on get their language from this
+    /// registry when the event doesn't say
+    SetLanguages(LanguageRegistry),
+    /// saves the active session and makes run() return, which closes the
+    /// channel of time entries once they are all sent
+    Shutdown,
+}
+
+/// a snapshot of the manager's state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManagerStatus {
+    pub paused: bool,
+    pub active_file: Option<PathBuf>,
+    pub sessions: Vec<SessionStatus>,
+}
+
+/// a snapshot of a single file session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionStatus {
+    pub path: PathBuf,
+    pub language: String,
+    pub project: Option<String>,
+    pub active: bool,
+    /// seconds the session has been running since it was last resumed
+    pub current_secs: u64,
+    /// seconds of every interval of the session, the running one included
+    pub total_secs: u64,
+    /// every interval of the session, oldest first. the running one ends now
+    pub intervals: Vec<Interval>,
+}
+
 // manage will save states of files that are open and the input activities that
 // are occuring inside each and any of them.
 // this object will record the time that each file has received attention and output that when prompted
 //
+// only one file is considered "active" at a time: the one that was last
+// opened, modified or focused. input activity keeps that file's session
+// running and idle events pause it. every time a session is paused, the
+// time it was running for is sent out as a TimeEntry
+#[derive(Debug)]
+pub struct Manager {
+    sessions: HashMap<PathBuf, FileSession>,
+    active_file: Option<PathBuf>,
+    entry_sender: UnboundedSender<TimeEntry>,
+    journal: Option<SharedJournal>,
+    last_input_heartbeat: Option<Instant>,
+    clock: SharedClock,
+    commands: Option<UnboundedReceiver<ManagerCommand>>,
+    // while paused every tracking event is dropped
+    paused: bool,
+    // used when an event doesn't say which language its file is in
+    languages: LanguageRegistry,
+}
+
+impl Manager {
+    /// creates a new manager and the receiver channel on which
+    /// finished time entries will be sent
+    pub fn new() -> (Self, UnboundedReceiver<TimeEntry>) {
+        let (tx, rx) = mpsc::unbounded_channel::<TimeEntry>();
+        (
+            Self {
+                sessions: HashMap::new(),
+                active_file: None,
+                entry_sender: tx,
+                journal: None,
+                last_input_heartbeat: None,
+                clock: SystemClock::shared(),
+                commands: None,
+                paused: false,
+                languages: LanguageRegistry::default(),
+            },
+            rx,
+        )
+    }
+
+    /// makes the manager write a heartbeat to the journal for every
+    /// file event and (rate limited) input activity
+    pub fn with_journal(mut self, journal: SharedJournal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// makes the manager take the time from the given clock instead of the system's
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// makes the manager detect the language of files the same way the file
+    /// watchers do, with the languages of the config
+    pub fn with_languages(mut self, languages: LanguageRegistry) -> Self {
+        self.languages = languages;
+        self
+    }
+
+    /// makes the manager answer the commands sent through the given channel
+    pub fn with_commands(mut self, commands: UnboundedReceiver<ManagerCommand>) -> Self {
+        self.commands = Some(commands);
+        self
+    }
+
+    /// consumes tracking events until every sender is dropped or it's told
+    /// to shut down, answering commands in between. the active session is
+    /// flushed before returning
+    #[instrument(name = "manager", skip_all)]
+    pub async fn run(mut self, mut events: UnboundedReceiver<TrackingEvents>) {
+        let mut commands = self.commands.take();
+        loop {
+            tokio::select! {
+                // events go first, so that a command sees every event sent
+                // before it and a shutdown doesn't drop the ones still queued
+                biased;
+                event = events.recv() => match event {
+                    Some(event) => self.handle_event(event),
+                    None => break,
+                },
+                Some(command) = next_command(&mut commands) => {
+                    let shutdown = matches!(command, ManagerCommand::Shutdown);
+                    self.handle_command(command);
+                    if shutdown {
+                        break;
+                    }
+                }
+            }
+        }
+        self.flush();
+    }
+
+    /// answers a single command
+    pub fn handle_command(&mut self, command: ManagerCommand) {
+        match command {
+            ManagerCommand::Status(reply) => {
+                // the asker may have given up waiting
+                let _ = reply.send(self.status());
+            }
+            ManagerCommand::Pause => {
+                self.pause_active();
+                self.paused = true;
+                info!("tracking paused");
+            }
+            ManagerCommand::Resume => {
+                self.paused = false;
+                info!("tracking resumed");
+            }
+            ManagerCommand::SetLanguages(languages) => self.languages = languages,
+            // nothing is tracked anymore, whatever comes after
+            ManagerCommand::Shutdown => {
+                self.flush();
+                self.paused = true;
+                info!("tracking stopped");
+            }
+        }
+    }
+
+    /// returns a snapshot of the sessions, active file first
+    pub fn status(&self) -> ManagerStatus {
+        let now = self.clock.now();
+        let mut sessions: Vec<SessionStatus> = self
+            .sessions
+            .values()
+            .map(|session| SessionStatus {
+                path: session.path.clone(),
+                language: session.language.clone(),
+                project: session.project.clone(),
+                active: session.is_active(),
+                current_secs: session
+                    .current_interval(now)
+                    .map(|interval| interval.duration().as_secs())
+                    .unwrap_or(0),
+                total_secs: session.total_duration(now).as_secs(),
+                intervals: session
+                    .intervals()
+                    .iter()
+                    .copied()
+                    .chain(session.current_interval(now))
+                    .collect(),
+            })
+            .collect();
+        sessions.sort_by_key(|session| {
+            (
+                self.active_file.as_ref() != Some(&session.path),
+                session.path.clone(),
+            )
+        });
+
+        ManagerStatus {
+            paused: self.paused,
+            active_file: self.active_file.clone(),
+            sessions,
+        }
+    }
+
+    /// updates the sessions based on a single tracking event
+    pub fn handle_event(&mut self, event: TrackingEvents) {
+        trace!(?event, "tracking event");
+        if self.paused {
+            return;
+        }
+        match event {
+            TrackingEvents::FileOpened { path, language } => {
+                self.open(PathBuf::from(path), language);
+            }
+            TrackingEvents::FileModified {
+                path,
+                language,
+                project,
+            }
+            | TrackingEvents::FileFocused {
+                path,
+                language,
+                project,
+            } => {
+                self.focus(PathBuf::from(path), language, project);
+            }
+            TrackingEvents::FileClosed { path } => self.close(&PathBuf::from(path)),
+            TrackingEvents::BufferClosed { path } => self.close_buffer(&PathBuf::from(path)),
+            TrackingEvents::FileRenamed { from, to, language } => {
+                self.rename(&PathBuf::from(from), PathBuf::from(to), language);
+            }
+            TrackingEvents::UserActive => self.resume_active(),
+            // the session stays paused until the next input, the user may
+            // not even be at the machine that woke up
+            TrackingEvents::SystemAwake => {}
+            // the user left when the last input was made, not when that
+            // was noticed
+            TrackingEvents::UserIdle { duration } => {
+                let last_input = self.clock.now().checked_sub(duration);
+                self.pause_active_at(last_input.unwrap_or(SystemTime::UNIX_EPOCH));
+            }
+            TrackingEvents::SystemSleep => self.pause_active(),
+        }
+    }
+
+    /// pauses the active session so that its time is sent out
+    pub fn flush(&mut self) {
+        self.pause_active();
+    }
+
+    /// makes the given file the active one. the previously active file
+    /// is paused and a session is created for the new file if needed.
+    /// a project given by the sender wins over the detected one
+    fn focus(&mut self, path: PathBuf, language: Option<String>, project: Option<String>) {
+        if self.active_file.as_ref() != Some(&path) {
+            self.pause_active();
+            self.active_file = Some(path.clone());
+        }
+
+        let now = self.clock.now();
+        let session = self.sessions.entry(path.clone()).or_insert_with(|| {
+            let language =
+                language.unwrap_or_else(|| self.languages.detect(&path).name().to_string());
+            FileSession::new(path, language, now)
+        });
+        if project.is_some() {
+            session.project = project;
+        }
+        session.resume(now);
+        self.heartbeat(HeartbeatKind::File, now);
+    }
+
+    /// makes sure there is a session for an opened file, without starting
+    /// it. every process that reads the file opens it, compilers, language
+    /// servers and search tools included, so an open says nothing about
+    /// where the user's attention is
+    fn open(&mut self, path: PathBuf, language: String) {
+        match self.sessions.get_mut(&path) {
+            Some(session) => session.language = language,
+            None => {
+                let session = FileSession::new_paused(path.clone(), language);
+                self.sessions.insert(path, session);
+            }
+        }
+    }
+
+    /// ends the session of a closed file. the time of the active file is
+    /// saved, but it stays the active file: editors close files right after
+    /// saving them while the buffer stays open, so the next input resumes
+    /// it. any other file is forgotten
+    fn close(&mut self, path: &Path) {
+        if self.active_file.as_deref() == Some(path) {
+            self.pause_active();
+            return;
+        }
+        self.sessions.remove(path);
+    }
+
+    /// ends the session of a file the editor closed and forgets about it
+    fn close_buffer(&mut self, path: &Path) {
+        if self.active_file.as_deref() == Some(path) {
+            self.pause_active();
+            self.active_file = None;
+        }
+        self.sessions.remove(path);
+    }
+
+    /// moves the session of a renamed file to its new path, keeping the time
+    /// it has been running for. the time up to the rename is saved under
+    /// the old path
+    fn rename(&mut self, from: &Path, to: PathBuf, language: String) {
+        let was_active = self.active_file.as_deref() == Some(from);
+        if was_active {
+            self.pause_active();
+        }
+        let Some(mut session) = self.sessions.remove(from) else {
+            return;
+        };
+
+        session.project = detect_project(&to);
+        session.path = to.clone();
+        session.language = language;
+        self.sessions.insert(to.clone(), session);
+        if was_active {
+            self.active_file = Some(to.clone());
+            self.focus(to, None, None);
+        }
+    }
+
+    /// resumes the active session on input activity. while the session
+    /// is running, input only shows up in the journal every so often
+    fn resume_active(&mut self) {
+        let Some(session) = self
+            .active_file
+            .as_ref()
+            .and_then(|path| self.sessions.get_mut(path))
+        else {
+            return;
+        };
+
+        let was_active = session.is_active();
+        session.resume(self.clock.now());
+        let now = self.clock.instant();
+        if was_active
+            && self
+                .last_input_heartbeat
+                .is_some_and(|last| now.duration_since(last) < INPUT_HEARTBEAT_INTERVAL)
+        {
+            return;
+        }
+        self.last_input_heartbeat = Some(now);
+        self.heartbeat(HeartbeatKind::Input, self.clock.now());
+    }
+
+    /// writes a heartbeat for the active file to the journal, if there is one
+    fn heartbeat(&mut self, kind: HeartbeatKind, time: SystemTime) {
+        let (Some(journal), Some(session)) = (
+            &self.journal,
+            self.active_file
+                .as_ref()
+                .and_then(|path| self.sessions.get(path)),
+        ) else {
+            return;
+        };
+
+        let heartbeat = Heartbeat::new(
+            kind,
+            session.path.clone(),
+            session.language.clone(),
+            session.project.clone(),
+            time,
+        );
+        let mut journal = journal.lock().expect("failed to get a lock on the journal");
+        if let Err(e) = journal.append(&heartbeat) {
+            warn!("failed to write heartbeat: {e:#}");
+        }
+    }
+
+    /// pauses the active session and sends the interval it was running
+    /// for since its last resume as a TimeEntry
+    fn pause_active(&mut self) {
+        self.pause_active_at(self.clock.now());
+    }
+
+    /// like pause_active, but the interval ends at the given time. an end
+    /// before the start of the interval leaves an empty one, which isn't sent
+    fn pause_active_at(&mut self, end: SystemTime) {
+        let Some(path) = self.active_file.clone() else {
+            return;
+        };
+        let Some(interval) = self
+            .sessions
+            .get_mut(&path)
+            .and_then(|session| session.pause(end))
+        else {
+            return;
+        };
+        // written for empty intervals too, or recovering the journal would
+        // count the time up to the next resume
+        self.heartbeat(HeartbeatKind::Paused, interval.end);
+
+        let duration = interval.duration();
+        if duration.is_zero() {
+            return;
+        }
+        let session = &self.sessions[&path];
+        let entry = TimeEntry {
+            path: session.path.clone(),
+            language: session.language.clone(),
+            project: session.project.clone(),
+            duration,
+            start_time: interval.start,
+            end_time: interval.end,
+        };
+        // the receiver being gone only means nobody is interested anymore
+        let _ = self.entry_sender.send(entry);
+    }
+}
+
+/// waits for the next command, or forever if there is no command channel
+async fn next_command(
+    commands: &mut Option<UnboundedReceiver<ManagerCommand>>,
+) -> Option<ManagerCommand> {
+    match commands {
+        Some(commands) => commands.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::MockClock, journal::Journal, language::LanguageConfig};
+
+    /// replays a timeline of (seconds since start, event) on a manager with a
+    /// mock clock. the manager is flushed at the end, like on shutdown
+    fn replay(timeline: &[(u64, TrackingEvents)]) -> (MockClock, Vec<TimeEntry>) {
+        let clock = MockClock::new();
+        let (manager, mut receiver) = Manager::new();
+        let mut manager = manager.with_clock(clock.shared());
+
+        for (at, event) in timeline {
+            let at = Duration::from_secs(*at);
+            assert!(at >= clock.elapsed(), "timeline must be in order");
+            clock.advance(at - clock.elapsed());
+            manager.handle_event(event.clone());
+        }
+        manager.flush();
+
+        let mut entries = Vec::new();
+        while let Ok(entry) = receiver.try_recv() {
+            entries.push(entry);
+        }
+        (clock, entries)
+    }
+
+    fn modified(path: &str) -> TrackingEvents {
+        TrackingEvents::FileModified {
+            path: path.to_string(),
+            language: Some("Rust".to_string()),
+            project: None,
+        }
+    }
+
+    /// idle detected 20 seconds after the last input
+    fn idle() -> TrackingEvents {
+        TrackingEvents::UserIdle {
+            duration: Duration::from_secs(20),
+        }
+    }
+
+    /// (path, seconds) of every entry
+    fn durations(entries: &[TimeEntry]) -> Vec<(String, u64)> {
+        entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.path.to_string_lossy().to_string(),
+                    entry.duration.as_secs(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn idle_ends_the_active_session() {
+        let (clock, entries) = replay(&[(0, modified("/work/a.rs")), (30, idle())]);
+
+        // the 20 idle seconds aren't work
+        assert_eq!(durations(&entries), [("/work/a.rs".to_string(), 10)]);
+        assert_eq!(entries[0].start_time, clock.start());
+        assert_eq!(entries[0].end_time, clock.start() + Duration::from_secs(10));
+    }
+
+    #[test]
+    fn idle_since_before_the_interval_started_tracks_nothing() {
+        let (_, entries) = replay(&[
+            (0, modified("/work/a.rs")),
+            (10, TrackingEvents::UserActive),
+            (30, idle()),
+            // resumed by a file event, idle counted from the input before it
+            (100, modified("/work/a.rs")),
+            (110, idle()),
+        ]);
+
+        assert_eq!(durations(&entries), [("/work/a.rs".to_string(), 10)]);
+    }
+
+    #[test]
+    fn empty_pauses_keep_idle_time_out_of_the_journal() {
+        let path =
+            std::env::temp_dir().join(format!("timeit-manager-journal-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        let journal = Journal::create(&Journal::path_in(&path)).unwrap().shared();
+        let clock = MockClock::new();
+        let (manager, _entries) = Manager::new();
+        let mut manager = manager.with_clock(clock.shared()).with_journal(journal);
+
+        manager.handle_event(modified("/work/a.rs"));
+        // idle since the session started, nothing to save
+        clock.advance(Duration::from_secs(20));
+        manager.handle_event(idle());
+        clock.advance(Duration::from_secs(3600));
+        manager.handle_event(TrackingEvents::UserActive);
+        clock.advance(INPUT_HEARTBEAT_INTERVAL);
+        manager.handle_event(TrackingEvents::UserActive);
+        // the watcher dies here
+        drop(manager);
+
+        let recovered = Journal::recover(&Journal::path_in(&path)).unwrap();
+        assert_eq!(
+            durations(&recovered),
+            [("/work/a.rs".to_string(), INPUT_HEARTBEAT_INTERVAL.as_secs())]
+        );
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn switching_files_splits_the_time() {
+        let (_, entries) = replay(&[
+            (0, modified("/work/a.rs")),
+            (10, modified("/work/b.rs")),
+            (25, TrackingEvents::UserActive),
+            (45, idle()),
+        ]);
+
+        assert_eq!(
+            durations(&entries),
+            [
+                ("/work/a.rs".to_string(), 10),
+                ("/work/b.rs".to_string(), 15)
+            ]
+        );
+    }
+
+    #[test]
+    fn activity_after_idle_resumes_the_last_file() {
+        let (_, entries) = replay(&[
+            (0, modified("/work/a.rs")),
+            (20, TrackingEvents::UserActive),
+            (40, idle()),
+            (100, TrackingEvents::UserActive),
+            (130, TrackingEvents::UserActive),
+            (150, idle()),
+        ]);
+
+        assert_eq!(
+            durations(&entries),
+            [
+                ("/work/a.rs".to_string(), 20),
+                ("/work/a.rs".to_string(), 30)
+            ]
+        );
+    }
+
+    #[test]
+    fn sleep_pauses_until_the_next_input() {
+        let (_, entries) = replay(&[
+            (0, modified("/work/a.rs")),
+            (5, TrackingEvents::SystemSleep),
+            (3605, TrackingEvents::SystemAwake),
+            (3615, TrackingEvents::UserActive),
+            (3625, TrackingEvents::UserActive),
+            (3645, idle()),
+        ]);
+
+        assert_eq!(
+            durations(&entries),
+            [
+                ("/work/a.rs".to_string(), 5),
+                ("/work/a.rs".to_string(), 10)
+            ]
+        );
+    }
+
+    #[test]
+    fn flush_saves_the_running_session() {
+        let (_, entries) = replay(&[
+            (0, modified("/work/a.rs")),
+            (45, TrackingEvents::UserActive),
+        ]);
+
+        assert_eq!(durations(&entries), [("/work/a.rs".to_string(), 45)]);
+    }
+
+    #[test]
+    fn input_without_a_file_tracks_nothing() {
+        let (_, entries) = replay(&[(0, TrackingEvents::UserActive), (10, idle())]);
+
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn nothing_is_tracked_while_paused() {
+        let clock = MockClock::new();
+        let (manager, mut receiver) = Manager::new();
+        let mut manager = manager.with_clock(clock.shared());
+
+        manager.handle_event(modified("/work/a.rs"));
+        clock.advance(Duration::from_secs(10));
+        manager.handle_command(ManagerCommand::Pause);
+        clock.advance(Duration::from_secs(10));
+        manager.handle_event(modified("/work/b.rs"));
+        manager.handle_event(TrackingEvents::UserActive);
+        clock.advance(Duration::from_secs(10));
+        assert!(manager.status().paused);
+        assert!(
+            manager
+                .status()
+                .sessions
+                .iter()
+                .all(|session| !session.active)
+        );
+
+        manager.handle_command(ManagerCommand::Resume);
+        manager.handle_event(TrackingEvents::UserActive);
+        clock.advance(Duration::from_secs(5));
+        manager.flush();
+
+        let mut entries = Vec::new();
+        while let Ok(entry) = receiver.try_recv() {
+            entries.push(entry);
+        }
+        assert_eq!(
+            durations(&entries),
+            [
+                ("/work/a.rs".to_string(), 10),
+                ("/work/a.rs".to_string(), 5)
+            ]
+        );
+    }
+
+    #[test]
+    fn renamed_file_carries_on_under_the_new_path() {
+        let (_, entries) = replay(&[
+            (0, modified("/work/a.rs")),
+            (
+                10,
+                TrackingEvents::FileRenamed {
+                    from: "/work/a.rs".to_string(),
+                    to: "/work/b.rs".to_string(),
+                    language: "Rust".to_string(),
+                },
+            ),
+            (20, TrackingEvents::UserActive),
+            (40, idle()),
+        ]);
+
+        assert_eq!(
+            durations(&entries),
+            [
+                ("/work/a.rs".to_string(), 10),
+                ("/work/b.rs".to_string(), 10)
+            ]
+        );
+    }
+
+    fn opened(path: &str) -> TrackingEvents {
+        TrackingEvents::FileOpened {
+            path: path.to_string(),
+            language: "Rust".to_string(),
+        }
+    }
+
+    fn closed(path: &str) -> TrackingEvents {
+        TrackingEvents::FileClosed {
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn opening_files_does_not_move_the_active_file() {
+        let clock = MockClock::new();
+        let (manager, mut receiver) = Manager::new();
+        let mut manager = manager.with_clock(clock.shared());
+
+        manager.handle_event(modified("/work/a.rs"));
+        // cargo check reading every file of the crate
+        clock.advance(Duration::from_secs(1));
+        for path in ["/work/b.rs", "/work/c.rs", "/work/d.rs", "/work/a.rs"] {
+            manager.handle_event(opened(path));
+            manager.handle_event(closed(path));
+        }
+        manager.handle_event(opened("/work/e.rs"));
+        manager.handle_event(TrackingEvents::UserActive);
+        clock.advance(Duration::from_secs(9));
+
+        let status = manager.status();
+        assert_eq!(status.active_file.as_deref(), Some(Path::new("/work/a.rs")));
+        let running: Vec<&Path> = status
+            .sessions
+            .iter()
+            .filter(|session| session.active)
+            .map(|session| session.path.as_path())
+            .collect();
+        assert_eq!(running, [Path::new("/work/a.rs")]);
+        // closing it ended one interval and the input started the next
+        let start = clock.start();
+        let secs = |secs| start + Duration::from_secs(secs);
+        assert_eq!(
+            status.sessions[0].intervals,
+            [
+                Interval::new(secs(0), secs(1)),
+                Interval::new(secs(1), secs(10))
+            ]
+        );
+        // closed ones are forgotten, the one still open waits
+        assert_eq!(status.sessions.len(), 2);
+
+        manager.flush();
+        let mut entries = Vec::new();
+        while let Ok(entry) = receiver.try_recv() {
+            entries.push(entry);
+        }
+        // the close of a.rs saved its first second, input resumed it
+        assert_eq!(
+            durations(&entries),
+            [("/work/a.rs".to_string(), 1), ("/work/a.rs".to_string(), 9)]
+        );
+    }
+
+    #[test]
+    fn closing_the_active_file_ends_its_session() {
+        let (_, entries) = replay(&[
+            (0, modified("/work/a.rs")),
+            (5, closed("/work/a.rs")),
+            // nothing runs until the next input
+            (30, TrackingEvents::UserActive),
+            (35, TrackingEvents::UserActive),
+            (55, idle()),
+        ]);
+
+        assert_eq!(
+            durations(&entries),
+            [("/work/a.rs".to_string(), 5), ("/work/a.rs".to_string(), 5)]
+        );
+    }
+
+    #[test]
+    fn languages_of_the_config_are_used_when_events_have_none() {
+        let config = |language: &str| LanguageConfig {
+            extensions: [("tpl".to_string(), language.to_string())].into(),
+            ..LanguageConfig::default()
+        };
+        let focused = |path: &str| TrackingEvents::FileFocused {
+            path: path.to_string(),
+            language: None,
+            project: None,
+        };
+        let (manager, _receiver) = Manager::new();
+        let mut manager = manager
+            .with_clock(MockClock::new().shared())
+            .with_languages(LanguageRegistry::new(&config("Template")));
+        let language = |manager: &Manager, path: &str| {
+            let status = manager.status();
+            let session = status
+                .sessions
+                .iter()
+                .find(|session| session.path == Path::new(path));
+            session.unwrap().language.clone()
+        };
+
+        manager.handle_event(focused("/work/page.tpl"));
+        assert_eq!(language(&manager, "/work/page.tpl"), "Template");
+
+        // a reload only changes sessions that start afterwards
+        let registry = LanguageRegistry::new(&config("Jinja"));
+        manager.handle_command(ManagerCommand::SetLanguages(registry));
+        manager.handle_event(focused("/work/mail.tpl"));
+        assert_eq!(language(&manager, "/work/page.tpl"), "Template");
+        assert_eq!(language(&manager, "/work/mail.tpl"), "Jinja");
+    }
+
+    #[tokio::test]
+    async fn shutdown_saves_the_active_session_and_stops() {
+        let clock = MockClock::new();
+        let (manager, mut entries) = Manager::new();
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
+        let (tracking_tx, tracking_rx) = mpsc::unbounded_channel();
+        let manager = manager
+            .with_clock(clock.shared())
+            .with_commands(command_rx)
+            .run(tracking_rx);
+        let manager = tokio::spawn(manager);
+
+        tracking_tx.send(modified("/work/a.rs")).unwrap();
+        // the status answer means the event before it was handled
+        let (reply, status) = oneshot::channel();
+        command_tx.send(ManagerCommand::Status(reply)).unwrap();
+        status.await.unwrap();
+        clock.advance(Duration::from_secs(42));
+        command_tx.send(ManagerCommand::Shutdown).unwrap();
+
+        // stops even though the tracking channel is still open
+        manager.await.unwrap();
+        let entry = entries.recv().await.unwrap();
+        assert_eq!(entry.duration, Duration::from_secs(42));
+        assert!(entries.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn commands_wait_for_the_events_sent_before_them() {
+        let clock = MockClock::new();
+        let (manager, mut entries) = Manager::new();
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
+        let (tracking_tx, tracking_rx) = mpsc::unbounded_channel();
+        let manager = manager
+            .with_clock(clock.shared())
+            .with_commands(command_rx)
+            .run(tracking_rx);
+
+        // queued before the manager gets to run
+        tracking_tx.send(modified("/work/a.rs")).unwrap();
+        let (reply, status) = oneshot::channel();
+        command_tx.send(ManagerCommand::Status(reply)).unwrap();
+        tracking_tx.send(modified("/work/b.rs")).unwrap();
+        let manager = tokio::spawn(manager);
+
+        // queued events are all handled before the next command
+        let status = status.await.unwrap();
+        assert_eq!(status.sessions.len(), 2);
+        assert_eq!(status.active_file.as_deref(), Some(Path::new("/work/b.rs")));
 
-#[derive(Debug, Clone)]
-pub struct Manager {}
+        // the idle event takes the time back. handling the shutdown before
+        // it would save 30 seconds
+        clock.advance(Duration::from_secs(30));
+        tracking_tx
+            .send(TrackingEvents::UserIdle {
+                duration: Duration::from_secs(30),
+            })
+            .unwrap();
+        command_tx.send(ManagerCommand::Shutdown).unwrap();
+        manager.await.unwrap();
+        assert!(entries.recv().await.is_none());
+    }
+}
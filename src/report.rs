@@ -0,0 +1,421 @@
+use anyhow::{Result, bail};
+use chrono::{Datelike, Days, Months, NaiveDate, Weekday};
+use std::{
+    collections::HashMap,
+    fmt::{self, Write},
+    hash::Hash,
+    time::Duration,
+};
+
+use crate::{
+    stats::{DailyStats, format_duration},
+    storage::Storage,
+};
+
+/// width of the percentage bars, in characters
+const BAR_WIDTH: usize = 20;
+/// names longer than this are shortened from the left, keeping their end
+const NAME_WIDTH: usize = 36;
+
+/// the days a report covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Day(NaiveDate),
+    /// the iso week (monday to sunday) of the date
+    Week(NaiveDate),
+    /// the month of the date
+    Month(NaiveDate),
+    Range(NaiveDate, NaiveDate),
+}
+
+impl Period {
+    /// first and last day of the period
+    pub fn range(&self) -> (NaiveDate, NaiveDate) {
+        match *self {
+            Period::Day(date) => (date, date),
+            Period::Week(date) => {
+                let week = date.week(Weekday::Mon);
+                (week.first_day(), week.last_day())
+            }
+            Period::Month(date) => {
+                let first = date.with_day(1).expect("every month has a first day");
+                let last = first + Months::new(1) - Days::new(1);
+                (first, last)
+            }
+            Period::Range(from, to) => (from, to),
+        }
+    }
+
+    pub fn title(&self) -> String {
+        let (from, to) = self.range();
+        match *self {
+            Period::Day(date) => format!("{date} ({})", date.format("%A")),
+            Period::Week(date) => {
+                let week = date.iso_week();
+                format!("week {}-W{:02}, {from} to {to}", week.year(), week.week())
+            }
+            Period::Month(date) => date.format("%B %Y").to_string(),
+            Period::Range(..) => format!("{from} to {to}"),
+        }
+    }
+}
+
+/// parses an iso week like 2024-W05 into its monday
+pub fn parse_week(value: &str) -> Result<NaiveDate, String> {
+    let invalid = || format!("{value:?} is not an iso week like 2024-W05");
+    let (year, week) = value.split_once("-W").ok_or_else(invalid)?;
+    let year = year.parse().map_err(|_| invalid())?;
+    let week = week.parse().map_err(|_| invalid())?;
+    NaiveDate::from_isoywd_opt(year, week, Weekday::Mon).ok_or_else(invalid)
+}
+
+/// parses a month like 2024-05 into its first day
+pub fn parse_month(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(&format!("{value}-01"), "%Y-%m-%d")
+        .map_err(|_| format!("{value:?} is not a month like 2024-05"))
+}
+
+/// the tracked time of a period, per day and summed up
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub period: Period,
+    /// days with tracked time only, oldest first
+    pub days: Vec<DailyStats>,
+    /// every day of the period added together
+    pub total: DailyStats,
+}
+
+impl Report {
+    pub fn build(storage: &Storage, period: Period) -> Result<Self> {
+        let (from, to) = period.range();
+        if from > to {
+            bail!("--from ({from}) must not be after --to ({to})");
+        }
+        let days = storage.daily_stats(from, to)?;
+
+        let mut total = DailyStats::new(period.title());
+        for day in &days {
+            total.merge(day);
+        }
+        Ok(Self {
+            period,
+            days,
+            total,
+        })
+    }
+
+    /// the day with the most tracked time
+    pub fn busiest_day(&self) -> Option<&DailyStats> {
+        self.days.iter().max_by_key(|day| day.total_time)
+    }
+
+    /// average time per day of the period, days without tracked time included
+    pub fn average_per_day(&self) -> Duration {
+        let (from, to) = self.period.range();
+        match u32::try_from((to - from).num_days() + 1) {
+            Ok(days) if days > 0 => self.total.total_time / days,
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// renders the report for the terminal, with at most top entries per breakdown
+    pub fn render(&self, top: usize) -> String {
+        let mut out = String::new();
+        self.write(&mut out, top)
+            .expect("writing to a string can't fail");
+        out
+    }
+
+    fn write(&self, out: &mut String, top: usize) -> fmt::Result {
+        let (from, to) = self.period.range();
+        writeln!(out, "{}", self.period.title())?;
+        if self.days.is_empty() {
+            return writeln!(out, "nothing tracked between {from} and {to}");
+        }
+
+        writeln!(out, "total: {}", format_duration(self.total.total_time))?;
+        if from != to {
+            writeln!(
+                out,
+                "average: {} per day over {} days, {} with tracked time",
+                format_duration(self.average_per_day()),
+                (to - from).num_days() + 1,
+                self.days.len()
+            )?;
+            if let Some(day) = self.busiest_day() {
+                writeln!(
+                    out,
+                    "busiest day: {} with {}",
+                    day.date,
+                    format_duration(day.total_time)
+                )?;
+            }
+        }
+
+        let total = self.total.total_time;
+        render_breakdown(
+            out,
+            "languages",
+            &self.total.entries_by_lang,
+            total,
+            top,
+            |lang| lang.clone(),
+        )?;
+        render_breakdown(
+            out,
+            "projects",
+            &self.total.entries_by_project,
+            total,
+            top,
+            |project| project.clone(),
+        )?;
+        render_breakdown(
+            out,
+            "files",
+            &self.total.entries_by_file,
+            total,
+            top,
+            |path| path.display().to_string(),
+        )?;
+
+        if self.days.len() > 1 {
+            // by date rather than by duration
+            writeln!(out, "\ndays:")?;
+            for day in &self.days {
+                writeln!(out, "  {}", bar_line(&day.date, day.total_time, total))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// renders the top entries of a breakdown, longest first
+fn render_breakdown<K: Eq + Hash>(
+    out: &mut String,
+    title: &str,
+    entries: &HashMap<K, Duration>,
+    total: Duration,
+    top: usize,
+    name: impl Fn(&K) -> String,
+) -> fmt::Result {
+    if entries.is_empty() || top == 0 {
+        return Ok(());
+    }
+    let mut entries: Vec<(String, Duration)> = entries
+        .iter()
+        .map(|(key, duration)| (name(key), *duration))
+        .collect();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    writeln!(out, "\n{title}:")?;
+    for (name, duration) in entries.iter().take(top) {
+        writeln!(out, "  {}", bar_line(name, *duration, total))?;
+    }
+    if entries.len() > top {
+        let rest: Duration = entries[top..].iter().map(|(_, duration)| *duration).sum();
+        writeln!(
+            out,
+            "  {}",
+            bar_line(&format!("{} more", entries.len() - top), rest, total)
+        )?;
+    }
+    Ok(())
+}
+
+/// a line like `Rust      ██████████░░░░░░░░░░  50%  1h 05m`
+pub fn bar_line(name: &str, duration: Duration, total: Duration) -> String {
+    let share = if total.is_zero() {
+        0.0
+    } else {
+        duration.as_secs_f64() / total.as_secs_f64()
+    };
+    let filled = ((share * BAR_WIDTH as f64).round() as usize).min(BAR_WIDTH);
+    format!(
+        "{:<NAME_WIDTH$} {}{} {:>4.0}%  {}",
+        shorten(name),
+        "█".repeat(filled),
+        "░".repeat(BAR_WIDTH - filled),
+        share * 100.0,
+        format_duration(duration)
+    )
+}
+
+/// keeps the end of long names, which for paths is the interesting part
+fn shorten(name: &str) -> String {
+    let chars = name.chars().count();
+    if chars <= NAME_WIDTH {
+        return name.to_string();
+    }
+    let tail: String = name.chars().skip(chars - (NAME_WIDTH - 1)).collect();
+    format!("…{tail}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::TimeEntry;
+    use std::{path::PathBuf, time::SystemTime};
+
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    /// a day with the given (language, file, minutes) entries
+    fn day(date: &str, entries: &[(&str, &str, u64)]) -> DailyStats {
+        let mut day = DailyStats::new(date.to_string());
+        for (language, file, minutes) in entries {
+            day.add_entry(&TimeEntry {
+                path: PathBuf::from(file),
+                language: language.to_string(),
+                project: Some("app".to_string()),
+                duration: Duration::from_secs(minutes * 60),
+                start_time: SystemTime::UNIX_EPOCH,
+                end_time: SystemTime::UNIX_EPOCH,
+            });
+        }
+        day
+    }
+
+    /// sums the days up like Report::build does
+    fn report_of(period: Period, days: Vec<DailyStats>) -> Report {
+        let mut total = DailyStats::new(period.title());
+        for day in &days {
+            total.merge(day);
+        }
+        Report {
+            period,
+            days,
+            total,
+        }
+    }
+
+    #[test]
+    fn iso_weeks_are_parsed_into_their_monday() {
+        assert_eq!(parse_week("2024-W05"), Ok(date("2024-01-29")));
+        assert_eq!(parse_week("2020-W53"), Ok(date("2020-12-28")));
+        // the first week of 2025 starts in 2024
+        assert_eq!(parse_week("2025-W01"), Ok(date("2024-12-30")));
+        for value in [
+            "2021-W53",
+            "2024-W00",
+            "2024-05",
+            "2024W05",
+            "W05",
+            "2024-Wfive",
+        ] {
+            assert!(parse_week(value).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn months_are_parsed_into_their_first_day() {
+        assert_eq!(parse_month("2024-05"), Ok(date("2024-05-01")));
+        for value in ["2024-13", "2024", "2024-05-02", "May 2024"] {
+            assert!(parse_month(value).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn periods_cover_whole_weeks_and_months() {
+        // a week across the turn of the year, asked for by its last day
+        let week = Period::Week(date("2021-01-03"));
+        assert_eq!(week.range(), (date("2020-12-28"), date("2021-01-03")));
+        assert_eq!(week.title(), "week 2020-W53, 2020-12-28 to 2021-01-03");
+        let week = Period::Week(date("2024-12-31"));
+        assert_eq!(week.range(), (date("2024-12-30"), date("2025-01-05")));
+        assert!(week.title().starts_with("week 2025-W01"));
+
+        assert_eq!(
+            Period::Month(date("2024-02-15")).range(),
+            (date("2024-02-01"), date("2024-02-29"))
+        );
+        assert_eq!(
+            Period::Month(date("2023-12-31")).range(),
+            (date("2023-12-01"), date("2023-12-31"))
+        );
+        assert_eq!(Period::Month(date("2023-12-31")).title(), "December 2023");
+        assert_eq!(
+            Period::Day(date("2024-05-01")).range(),
+            (date("2024-05-01"), date("2024-05-01"))
+        );
+    }
+
+    #[test]
+    fn average_counts_every_day_of_the_period() {
+        let week = Period::Week(date("2024-01-29"));
+        let report = report_of(
+            week,
+            vec![day("2024-01-31", &[("Rust", "/app/main.rs", 480)])],
+        );
+
+        assert_eq!(report.average_per_day(), Duration::from_secs(8 * 3600) / 7);
+        let rendered = report.render(5);
+        assert!(
+            rendered.contains("average: 1h 08m per day over 7 days, 1 with tracked time"),
+            "{rendered}"
+        );
+
+        let empty = report_of(week, Vec::new());
+        assert_eq!(empty.average_per_day(), Duration::ZERO);
+        assert!(empty.render(5).contains("nothing tracked between"));
+    }
+
+    #[test]
+    fn busiest_day_has_the_most_time() {
+        let report = report_of(
+            Period::Week(date("2024-01-29")),
+            vec![
+                day("2024-01-29", &[("Rust", "/app/main.rs", 30)]),
+                day(
+                    "2024-01-30",
+                    &[("Rust", "/app/main.rs", 20), ("Go", "/app/main.go", 40)],
+                ),
+                day("2024-02-01", &[("Go", "/app/main.go", 45)]),
+            ],
+        );
+
+        assert_eq!(report.busiest_day().unwrap().date, "2024-01-30");
+        assert!(
+            report
+                .render(5)
+                .contains("busiest day: 2024-01-30 with 1h 00m")
+        );
+        assert!(
+            report_of(Period::Day(date("2024-01-29")), Vec::new())
+                .busiest_day()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn entries_past_the_top_are_summed_up() {
+        let report = report_of(
+            Period::Day(date("2024-01-29")),
+            vec![day(
+                "2024-01-29",
+                &[
+                    ("Rust", "/app/main.rs", 40),
+                    ("Go", "/app/main.go", 30),
+                    ("Python", "/app/main.py", 20),
+                    ("Lua", "/app/init.lua", 10),
+                ],
+            )],
+        );
+
+        let rendered = report.render(2);
+        let languages: Vec<&str> = rendered
+            .lines()
+            .skip_while(|line| *line != "languages:")
+            .skip(1)
+            .take_while(|line| !line.is_empty())
+            .collect();
+        assert_eq!(languages.len(), 3, "{rendered}");
+        assert!(languages[0].trim_start().starts_with("Rust"));
+        assert!(languages[1].trim_start().starts_with("Go"));
+        assert!(languages[2].trim_start().starts_with("2 more"));
+        assert!(languages[2].ends_with("30m 00s"), "{}", languages[2]);
+
+        // nothing is cut when everything fits
+        assert!(!report.render(4).contains("more"));
+    }
+}
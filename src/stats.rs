@@ -1,4 +1,9 @@
-use std::{collections::HashMap, path::PathBuf, time::SystemTime};
+use chrono::{DateTime, Local};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    time::SystemTime,
+};
 use tokio::time::Duration;
 
 #[derive(Debug, Clone)]
@@ -51,4 +56,48 @@ impl DailyStats {
                 .or_insert(Duration::ZERO) += entry.duration;
         }
     }
+
+    /// adds the totals of other to this one, used to sum up several days
+    pub fn merge(&mut self, other: &DailyStats) {
+        self.total_time += other.total_time;
+        for (lang, duration) in &other.entries_by_lang {
+            *self.entries_by_lang.entry(lang.clone()).or_default() += *duration;
+        }
+        for (project, duration) in &other.entries_by_project {
+            *self.entries_by_project.entry(project.clone()).or_default() += *duration;
+        }
+        for (path, duration) in &other.entries_by_file {
+            *self.entries_by_file.entry(path.clone()).or_default() += *duration;
+        }
+    }
+}
+
+/// returns the local date of the given time in the format used by DailyStats
+pub fn entry_date(time: SystemTime) -> String {
+    DateTime::<Local>::from(time).format("%Y-%m-%d").to_string()
+}
+
+/// sums up the entries per local day of their start, oldest day first
+pub fn group_by_day(entries: &[TimeEntry]) -> Vec<DailyStats> {
+    let mut days: BTreeMap<String, DailyStats> = BTreeMap::new();
+    for entry in entries {
+        let date = entry_date(entry.start_time);
+        days.entry(date.clone())
+            .or_insert_with(|| DailyStats::new(date))
+            .add_entry(entry);
+    }
+    days.into_values().collect()
+}
+
+/// formats a duration the way it is shown to users, e.g. "1h 05m" or "42s"
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{hours}h {minutes:02}m")
+    } else if minutes > 0 {
+        format!("{minutes}m {seconds:02}s")
+    } else {
+        format!("{seconds}s")
+    }
 }
@@ -0,0 +1,291 @@
+use anyhow::{Context, Result, bail};
+use chrono::{Local, NaiveDate, TimeZone};
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::stats::{DailyStats, TimeEntry, group_by_day};
+
+// every entry of this list is one schema version. the database keeps the
+// number of applied migrations in `PRAGMA user_version`, so new migrations
+// must only ever be appended to the end of this list
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema
+    "CREATE TABLE time_entries (
+        id          INTEGER PRIMARY KEY AUTOINCREMENT,
+        path        TEXT    NOT NULL,
+        language    TEXT    NOT NULL,
+        project     TEXT,
+        start_time  INTEGER NOT NULL,
+        end_time    INTEGER NOT NULL,
+        duration_ms INTEGER NOT NULL
+    );
+    CREATE INDEX idx_time_entries_start_time ON time_entries (start_time);",
+];
+
+/// sqlite backed storage for time entries.
+/// all timestamps are saved as milliseconds since unix epoch
+#[derive(Debug)]
+pub struct Storage {
+    conn: Connection,
+}
+
+impl Storage {
+    /// opens (or creates) the database at the given path and brings
+    /// its schema up to date
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        let conn = Connection::open(path)
+            .with_context(|| format!("failed to open database at {}", path.display()))?;
+
+        let mut storage = Self { conn };
+        storage.migrate()?;
+        Ok(storage)
+    }
+
+    /// location of the database inside the given data directory
+    pub fn path_in(data_dir: &Path) -> PathBuf {
+        data_dir.join("timeit.db")
+    }
+
+    /// returns the schema version the database is currently at
+    pub fn schema_version(&self) -> Result<usize> {
+        let version: i64 = self
+            .conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .context("failed to read schema version")?;
+        Ok(version as usize)
+    }
+
+    /// applies every migration that has not been applied yet.
+    /// each migration runs inside its own transaction
+    fn migrate(&mut self) -> Result<()> {
+        let current = self.schema_version()?;
+        if current > MIGRATIONS.len() {
+            bail!(
+                "database schema version {current} is newer than the latest known version {}",
+                MIGRATIONS.len()
+            );
+        }
+
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(current) {
+            let version = index + 1;
+            let tx = self.conn.transaction()?;
+            tx.execute_batch(migration)
+                .with_context(|| format!("failed to apply migration {version}"))?;
+            tx.pragma_update(None, "user_version", version as i64)?;
+            tx.commit()
+                .with_context(|| format!("failed to commit migration {version}"))?;
+        }
+        Ok(())
+    }
+
+    /// saves a single time entry
+    pub fn insert_entry(&self, entry: &TimeEntry) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT INTO time_entries (path, language, project, start_time, end_time, duration_ms)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    entry.path.to_string_lossy(),
+                    entry.language,
+                    entry.project,
+                    to_millis(entry.start_time),
+                    to_millis(entry.end_time),
+                    entry.duration.as_millis() as i64,
+                ],
+            )
+            .context("failed to insert time entry")?;
+        Ok(())
+    }
+
+    /// returns every entry that started within [from, to), ordered by start time
+    pub fn entries_between(&self, from: SystemTime, to: SystemTime) -> Result<Vec<TimeEntry>> {
+        let mut statement = self.conn.prepare(
+            "SELECT path, language, project, start_time, end_time, duration_ms
+             FROM time_entries
+             WHERE start_time >= ?1 AND start_time < ?2
+             ORDER BY start_time",
+        )?;
+        let entries = statement
+            .query_map(params![to_millis(from), to_millis(to)], entry_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .context("failed to read time entries")?;
+        Ok(entries)
+    }
+
+    /// returns every entry that started between the days from and to (both inclusive).
+    /// days are in local time
+    pub fn entries_for_days(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<TimeEntry>> {
+        self.entries_between(
+            local_midnight(from)?,
+            local_midnight(to.succ_opt().context("date out of range")?)?,
+        )
+    }
+
+    /// returns the most recently started entry, if anything was ever tracked
+    pub fn last_entry(&self) -> Result<Option<TimeEntry>> {
+        self.conn
+            .query_row(
+                "SELECT path, language, project, start_time, end_time, duration_ms
+                 FROM time_entries
+                 ORDER BY start_time DESC
+                 LIMIT 1",
+                [],
+                entry_from_row,
+            )
+            .optional()
+            .context("failed to read the last time entry")
+    }
+
+    /// rebuilds the DailyStats of every day between from and to (both inclusive)
+    /// on which something was tracked. days are in local time
+    pub fn daily_stats(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<DailyStats>> {
+        Ok(group_by_day(&self.entries_for_days(from, to)?))
+    }
+}
+
+/// directory in which the database and the journal are kept by default
+pub fn default_data_dir() -> Result<PathBuf> {
+    let data_dir = dirs::data_dir().context("failed to find the user data directory")?;
+    Ok(data_dir.join("timeit"))
+}
+
+fn entry_from_row(row: &Row) -> rusqlite::Result<TimeEntry> {
+    let path: String = row.get(0)?;
+    Ok(TimeEntry {
+        path: PathBuf::from(path),
+        language: row.get(1)?,
+        project: row.get(2)?,
+        start_time: from_millis(row.get(3)?),
+        end_time: from_millis(row.get(4)?),
+        duration: Duration::from_millis(row.get::<_, i64>(5)?.max(0) as u64),
+    })
+}
+
+fn to_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+fn from_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+/// start of the given day in local time
+fn local_midnight(date: NaiveDate) -> Result<SystemTime> {
+    let midnight = date.and_hms_opt(0, 0, 0).context("invalid date")?;
+    let local = Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .with_context(|| format!("{date} has no local midnight"))?;
+    Ok(local.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// path of a database no other test uses, removed first
+    fn temp_db(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("timeit-storage-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.join("timeit.db")
+    }
+
+    fn entry(path: &str, start: SystemTime, secs: u64) -> TimeEntry {
+        TimeEntry {
+            path: PathBuf::from(path),
+            language: "Rust".to_string(),
+            project: Some("app".to_string()),
+            duration: Duration::from_secs(secs),
+            start_time: start,
+            end_time: start + Duration::from_secs(secs),
+        }
+    }
+
+    #[test]
+    fn fresh_database_gets_every_migration() {
+        let storage = Storage::open(&temp_db("fresh")).unwrap();
+        assert_eq!(storage.schema_version().unwrap(), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn reopening_changes_nothing() {
+        let path = temp_db("reopen");
+        let start = local_midnight(NaiveDate::from_ymd_opt(2026, 3, 1).unwrap()).unwrap();
+        Storage::open(&path)
+            .unwrap()
+            .insert_entry(&entry("/app/main.rs", start, 60))
+            .unwrap();
+
+        let storage = Storage::open(&path).unwrap();
+        assert_eq!(storage.schema_version().unwrap(), MIGRATIONS.len());
+        assert_eq!(
+            storage.last_entry().unwrap().unwrap().path,
+            Path::new("/app/main.rs")
+        );
+    }
+
+    #[test]
+    fn newer_schema_is_rejected() {
+        let path = temp_db("newer");
+        drop(Storage::open(&path).unwrap());
+        Connection::open(&path)
+            .unwrap()
+            .pragma_update(None, "user_version", MIGRATIONS.len() as i64 + 1)
+            .unwrap();
+
+        let error = Storage::open(&path).unwrap_err();
+        assert!(format!("{error:#}").contains("newer"), "{error:#}");
+    }
+
+    #[test]
+    fn entries_belong_to_the_local_day_they_started_on() {
+        let storage = Storage::open(&temp_db("days")).unwrap();
+        let day = NaiveDate::from_ymd_opt(2026, 3, 10).unwrap();
+        let midnight = local_midnight(day).unwrap();
+        let next_midnight = local_midnight(day.succ_opt().unwrap()).unwrap();
+        let millisecond = Duration::from_millis(1);
+
+        let entries = [
+            entry("/app/before.rs", midnight - millisecond, 5),
+            entry("/app/first.rs", midnight, 5),
+            // runs into the next day, still counts for the day it started on
+            entry("/app/last.rs", next_midnight - millisecond, 5),
+            entry("/app/after.rs", next_midnight, 5),
+        ];
+        for entry in &entries {
+            storage.insert_entry(entry).unwrap();
+        }
+
+        let found = storage.entries_for_days(day, day).unwrap();
+        let paths: Vec<&Path> = found.iter().map(|entry| entry.path.as_path()).collect();
+        assert_eq!(
+            paths,
+            [Path::new("/app/first.rs"), Path::new("/app/last.rs")]
+        );
+        // everything comes back as it was saved
+        let last = &found[1];
+        assert_eq!(last.language, "Rust");
+        assert_eq!(last.project.as_deref(), Some("app"));
+        assert_eq!(last.start_time, entries[2].start_time);
+        assert_eq!(last.end_time, entries[2].end_time);
+        assert_eq!(last.duration, Duration::from_secs(5));
+
+        assert_eq!(
+            storage
+                .entries_for_days(day, day.succ_opt().unwrap())
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use std::path::{Path, PathBuf};
+
+/// suffixes of swap files. their names also start with a dot, which keeps
+/// files like flash's .swc apart. vim goes from .swp down to .swa
+const SWAP_SUFFIXES: &[&str] = &[
+    ".swp",
+    ".swo",
+    ".swn",
+    ".swm",
+    ".swl",
+    ".swk",
+    ".swj",
+    ".swi",
+    ".swh",
+    ".swg",
+    ".swf",
+    ".swe",
+    ".swd",
+    ".swc",
+    ".swb",
+    ".swa",
+    ".swx",
+    ".kate-swp",
+];
+
+/// suffixes editors add to the name of the file they are working on for
+/// backup and temporary files
+const TEMP_SUFFIXES: &[&str] = &["___jb_tmp___", "___jb_old___", ".tmp", ".bak"];
+
+/// checks whether the path is a swap, backup or temporary file of an editor
+pub fn is_temp_file(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    // vim writes 4913 to see if it may create files in the directory and
+    // gedit writes to .goutputstream-XXXXXX before renaming it
+    name == "4913" || name.starts_with(".goutputstream-") || original_name(name).is_some()
+}
+
+/// returns the file a swap, backup or temporary file stands for, if its
+/// name gives it away. e.g. `.main.rs.swp`, `main.rs~`, `.#main.rs` and
+/// `main.rs.tmp.1234` all stand for `main.rs` in the same directory
+pub fn original_path(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let original = original_name(name)?;
+    // temporary files are often hidden even when the file they belong to isn't
+    if let Some(visible) = original.strip_prefix('.')
+        && !path.with_file_name(original).exists()
+        && path.with_file_name(visible).exists()
+    {
+        return Some(path.with_file_name(visible));
+    }
+    Some(path.with_file_name(original))
+}
+
+fn original_name(name: &str) -> Option<&str> {
+    // emacs lock files
+    if let Some(original) = name.strip_prefix(".#") {
+        return non_empty(original);
+    }
+    // emacs auto-save files
+    if name.len() > 2 && name.starts_with('#') && name.ends_with('#') {
+        return non_empty(&name[1..name.len() - 1]);
+    }
+    // backups, including numbered ones like main.rs.~1~
+    if let Some(original) = name.strip_suffix('~') {
+        let original = match original.rfind(".~") {
+            Some(index) if original[index + 2..].chars().all(|c| c.is_ascii_digit()) => {
+                &original[..index]
+            }
+            _ => original,
+        };
+        return non_empty(original);
+    }
+    // temporary files with a random or pid suffix, like main.rs.tmp.1234.
+    // main.tmp.rs is a source file of its own
+    if let Some((original, suffix)) = name.rsplit_once(".tmp.")
+        && is_random_suffix(suffix)
+    {
+        return non_empty(original);
+    }
+    if let Some(hidden) = name.strip_prefix('.')
+        && let Some(original) = SWAP_SUFFIXES
+            .iter()
+            .find_map(|suffix| hidden.strip_suffix(suffix))
+    {
+        return non_empty(original);
+    }
+    TEMP_SUFFIXES
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .and_then(non_empty)
+}
+
+/// a pid, or the kind of random letters and digits mkstemp picks
+fn is_random_suffix(suffix: &str) -> bool {
+    let digits = suffix.chars().filter(char::is_ascii_digit).count();
+    suffix.chars().all(|c| c.is_ascii_alphanumeric())
+        && (digits == suffix.len() || (suffix.len() >= 6 && digits > 0))
+}
+
+fn non_empty(name: &str) -> Option<&str> {
+    (!name.is_empty()).then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn editor_files_stand_for_their_original() {
+        let cases = [
+            // vim and kate swap files
+            (".main.rs.swp", "main.rs"),
+            (".main.rs.swo", "main.rs"),
+            (".main.rs.swa", "main.rs"),
+            (".main.rs.swx", "main.rs"),
+            (".main.rs.kate-swp", "main.rs"),
+            // backups
+            ("main.rs~", "main.rs"),
+            ("main.rs.~1~", "main.rs"),
+            ("main.rs.~12~", "main.rs"),
+            ("main.rs.bak", "main.rs"),
+            // emacs lock and auto-save files
+            (".#main.rs", "main.rs"),
+            ("#main.rs#", "main.rs"),
+            // temporary files
+            ("main.rs.tmp", "main.rs"),
+            ("main.rs.tmp.1234", "main.rs"),
+            ("main.rs.tmp.a8Xk2q", "main.rs"),
+            ("main.rs___jb_tmp___", "main.rs"),
+            ("main.rs___jb_old___", "main.rs"),
+        ];
+        for (name, original) in cases {
+            assert_eq!(original_name(name), Some(original), "{name}");
+            assert!(is_temp_file(Path::new(name)), "{name}");
+        }
+    }
+
+    #[test]
+    fn probes_of_editors_are_temp_files() {
+        assert!(is_temp_file(Path::new("/work/4913")));
+        assert!(is_temp_file(Path::new("/work/.goutputstream-ABC123")));
+        assert_eq!(original_path(Path::new("/work/4913")), None);
+    }
+
+    #[test]
+    fn source_files_are_not_temp_files() {
+        let names = [
+            "main.rs",
+            "foo.tmp.rs",
+            "data.tmp.json",
+            "main.tmp.backup",
+            // flash components and a swap without its dot
+            "widgets.swc",
+            "main.rs.swp",
+            "~",
+            ".#",
+            "##",
+            ".swp",
+        ];
+        for name in names {
+            assert!(!is_temp_file(Path::new(name)), "{name}");
+        }
+    }
+
+    #[test]
+    fn hidden_temp_files_of_visible_files() {
+        let dir = std::env::temp_dir().join(format!("timeit-temp-files-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("main.rs"), "").unwrap();
+        std::fs::write(dir.join(".env"), "").unwrap();
+
+        assert_eq!(
+            original_path(&dir.join(".main.rs.tmp")),
+            Some(dir.join("main.rs"))
+        );
+        assert_eq!(
+            original_path(&dir.join(".main.rs.swp")),
+            Some(dir.join("main.rs"))
+        );
+        // hidden files keep their dot
+        assert_eq!(original_path(&dir.join(".env.tmp")), Some(dir.join(".env")));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
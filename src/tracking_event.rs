@@ -2,14 +2,45 @@ use tokio::time::Duration;
 
 #[derive(Debug, Clone)]
 pub enum TrackingEvents {
-    FileOpened { path: String, language: String },
-    FileClosed { path: String },
-    FileModified { path: String },
-    FileFocused { path: String },
+    FileOpened {
+        path: String,
+        language: String,
+    },
+    FileClosed {
+        path: String,
+    },
+    // an editor closed its buffer of the file. unlike FileClosed, which
+    // only means a handle of the file was closed, this ends its session
+    BufferClosed {
+        path: String,
+    },
+    // the session of the old path carries on under the new one
+    FileRenamed {
+        from: String,
+        to: String,
+        language: String,
+    },
+    // language and project are None when the sender doesn't know them
+    FileModified {
+        path: String,
+        language: Option<String>,
+        project: Option<String>,
+    },
+    FileFocused {
+        path: String,
+        language: Option<String>,
+        project: Option<String>,
+    },
 
     UserActive,
-    UserIdle { duration: Duration },
+    UserIdle {
+        duration: Duration,
+    },
 
+    // nothing sends these yet, they are there for a suspend source. the
+    // manager pauses on sleep and waits for input after waking up
+    #[allow(dead_code)]
     SystemAwake,
+    #[allow(dead_code)]
     SystemSleep,
 }
@@ -0,0 +1,418 @@
+use anyhow::Result;
+use chrono::Local;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::{
+    DefaultTerminal, Frame,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Paragraph},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+use tokio::sync::{
+    mpsc::{self, UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
+use tracing::Level;
+
+use crate::{
+    clock::SharedClock,
+    event_log,
+    input_watcher::{ActivityState, InputMonitor},
+    manager::{ManagerCommand, ManagerStatus},
+    report::bar_line,
+    stats::{DailyStats, format_duration},
+    storage::Storage,
+};
+
+/// how often the dashboard is redrawn when no key is pressed
+const REFRESH: Duration = Duration::from_millis(500);
+/// how many projects are listed at most
+const PROJECTS: usize = 6;
+const NO_PROJECT: &str = "no project";
+
+/// a live view of the watcher in the terminal. the event log is shown at the
+/// bottom, so logging must go to Output::Dashboard while it is drawn
+#[derive(Debug)]
+pub struct Dashboard {
+    manager: UnboundedSender<ManagerCommand>,
+    // a connection of its own, like the daemon's
+    storage: Arc<Mutex<Storage>>,
+    input_monitor: Arc<InputMonitor>,
+    clock: SharedClock,
+    // lines scrolled up from the newest, 0 follows the log
+    scroll: usize,
+    paused: bool,
+}
+
+/// everything that is drawn, taken at once
+#[derive(Debug, Clone)]
+struct Snapshot {
+    status: ManagerStatus,
+    today: DailyStats,
+    activity: Option<ActivityState>,
+    now: SystemTime,
+}
+
+impl Snapshot {
+    /// time of the sessions that is still running and not saved yet
+    fn running(&self) -> Duration {
+        self.status
+            .sessions
+            .iter()
+            .filter(|session| session.active)
+            .map(|session| Duration::from_secs(session.current_secs))
+            .sum()
+    }
+
+    fn today_total(&self) -> Duration {
+        self.today.total_time + self.running()
+    }
+
+    /// today's time per project, the running session included, longest first
+    fn projects(&self) -> Vec<(String, Duration)> {
+        let mut projects: HashMap<String, Duration> = self.today.entries_by_project.clone();
+        let with_project: Duration = projects.values().sum();
+        let without_project = self.today.total_time.saturating_sub(with_project);
+        if !without_project.is_zero() {
+            projects.insert(NO_PROJECT.to_string(), without_project);
+        }
+        for session in self.status.sessions.iter().filter(|session| session.active) {
+            let project = session.project.as_deref().unwrap_or(NO_PROJECT);
+            *projects.entry(project.to_string()).or_default() +=
+                Duration::from_secs(session.current_secs);
+        }
+
+        let mut projects: Vec<_> = projects
+            .into_iter()
+            .filter(|(_, duration)| !duration.is_zero())
+            .collect();
+        projects.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        projects
+    }
+}
+
+impl Dashboard {
+    pub fn new(
+        manager: UnboundedSender<ManagerCommand>,
+        storage: Storage,
+        input_monitor: Arc<InputMonitor>,
+        clock: SharedClock,
+    ) -> Self {
+        Self {
+            manager,
+            storage: Arc::new(Mutex::new(storage)),
+            input_monitor,
+            clock,
+            scroll: 0,
+            paused: false,
+        }
+    }
+
+    /// draws the dashboard until q is pressed. the terminal is restored
+    /// afterwards, also when drawing fails
+    pub async fn run(mut self) -> Result<()> {
+        // crossterm blocks while waiting for keys
+        let (key_sender, mut keys) = mpsc::unbounded_channel();
+        std::thread::spawn(move || {
+            while let Ok(event) = event::read() {
+                if let Event::Key(key) = event
+                    && key.kind == KeyEventKind::Press
+                    && key_sender.send(key).is_err()
+                {
+                    return;
+                }
+            }
+        });
+
+        let mut terminal = ratatui::init();
+        let result = self.draw_until_quit(&mut terminal, &mut keys).await;
+        ratatui::restore();
+        result
+    }
+
+    async fn draw_until_quit(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        keys: &mut UnboundedReceiver<KeyEvent>,
+    ) -> Result<()> {
+        let mut refresh = tokio::time::interval(REFRESH);
+        loop {
+            let snapshot = self.snapshot().await?;
+            self.paused = snapshot.status.paused;
+            terminal.draw(|frame| self.draw(frame, &snapshot))?;
+
+            tokio::select! {
+                _ = refresh.tick() => {}
+                key = keys.recv() => {
+                    let Some(key) = key else {
+                        return Ok(());
+                    };
+                    if !self.handle_key(key) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    /// returns false if the dashboard should close
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            // raw mode keeps ctrl+c from interrupting us
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Char('p') => {
+                let command = if self.paused {
+                    ManagerCommand::Resume
+                } else {
+                    ManagerCommand::Pause
+                };
+                let _ = self.manager.send(command);
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.scroll += 1,
+            KeyCode::Down | KeyCode::Char('j') => self.scroll = self.scroll.saturating_sub(1),
+            KeyCode::PageUp => self.scroll += 10,
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::Home | KeyCode::Char('g') => self.scroll = usize::MAX,
+            KeyCode::End | KeyCode::Char('G') => self.scroll = 0,
+            _ => {}
+        }
+        true
+    }
+
+    async fn snapshot(&self) -> Result<Snapshot> {
+        let (reply, status) = oneshot::channel();
+        self.manager
+            .send(ManagerCommand::Status(reply))
+            .map_err(|_| anyhow::anyhow!("the manager is shutting down"))?;
+        let status = status.await?;
+
+        // sqlite is blocking, so the query runs off the async workers
+        let storage = self.storage.clone();
+        let today = tokio::task::spawn_blocking(move || {
+            let today = Local::now().date_naive();
+            let storage = storage.lock().expect("failed to get a lock on storage");
+            Ok::<_, anyhow::Error>(
+                storage
+                    .daily_stats(today, today)?
+                    .into_iter()
+                    .next()
+                    .unwrap_or_else(|| DailyStats::new(today.to_string())),
+            )
+        })
+        .await??;
+
+        Ok(Snapshot {
+            status,
+            today,
+            activity: self.input_monitor.get_state(),
+            now: self.clock.now(),
+        })
+    }
+
+    fn draw(&mut self, frame: &mut Frame, snapshot: &Snapshot) {
+        let projects = snapshot.projects();
+        let [top, projects_area, log_area, help_area] = Layout::vertical([
+            Constraint::Length(6),
+            Constraint::Length(projects.len().clamp(1, PROJECTS) as u16 + 2),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [tracking_area, session_area] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(top);
+
+        frame.render_widget(tracking(snapshot), tracking_area);
+        frame.render_widget(session(snapshot), session_area);
+        frame.render_widget(
+            project_lines(&projects, snapshot.today_total()),
+            projects_area,
+        );
+        self.draw_log(frame, log_area);
+        frame.render_widget(
+            Paragraph::new(" q quit  p pause/resume  ↑/↓ scroll  home/end oldest/newest").dim(),
+            help_area,
+        );
+    }
+
+    fn draw_log(&mut self, frame: &mut Frame, area: Rect) {
+        let lines = event_log::lines();
+        let height = area.height.saturating_sub(2) as usize;
+        self.scroll = self.scroll.min(lines.len().saturating_sub(height));
+        let end = lines.len() - self.scroll;
+        let start = end.saturating_sub(height);
+
+        let title = if self.scroll > 0 {
+            format!(" events ({} newer) ", self.scroll)
+        } else {
+            " events ".to_string()
+        };
+        let lines: Vec<Line> = lines[start..end]
+            .iter()
+            .map(|line| {
+                let style = match line.level {
+                    Level::ERROR => Style::default().fg(Color::Red),
+                    Level::WARN => Style::default().fg(Color::Yellow),
+                    Level::INFO => Style::default(),
+                    _ => Style::default().fg(Color::DarkGray),
+                };
+                Line::from(vec![
+                    Span::styled(
+                        line.time.format("%H:%M:%S ").to_string(),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                    Span::styled(line.message.clone(), style),
+                ])
+            })
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(title)),
+            area,
+        );
+    }
+}
+
+fn tracking(snapshot: &Snapshot) -> Paragraph<'static> {
+    let state = if snapshot.status.paused {
+        Span::styled("paused", Style::default().fg(Color::Yellow))
+    } else {
+        Span::styled("tracking", Style::default().fg(Color::Green))
+    };
+    let activity = match &snapshot.activity {
+        Some(activity) => {
+            let since = activity
+                .time_since_last_activity(snapshot.now)
+                .map(|since| format!(" ({} ago)", format_duration(since)))
+                .unwrap_or_default();
+            if activity.is_idle {
+                Span::styled(format!("idle{since}"), Style::default().fg(Color::Yellow))
+            } else {
+                Span::styled(format!("active{since}"), Style::default().fg(Color::Green))
+            }
+        }
+        None => Span::raw("unknown"),
+    };
+
+    Paragraph::new(vec![
+        Line::from(vec![Span::raw("state:    "), state]),
+        Line::from(vec![Span::raw("input:    "), activity]),
+        Line::from(vec![
+            Span::raw("today:    "),
+            Span::styled(
+                format_duration(snapshot.today_total()),
+                Style::default().add_modifier(Modifier::BOLD),
+            ),
+        ]),
+        Line::from(format!("sessions: {}", snapshot.status.sessions.len())),
+    ])
+    .block(Block::bordered().title(" watcher "))
+}
+
+fn session(snapshot: &Snapshot) -> Paragraph<'static> {
+    let block = Block::bordered().title(" active file ");
+    let active = snapshot
+        .status
+        .sessions
+        .iter()
+        .find(|session| snapshot.status.active_file.as_ref() == Some(&session.path));
+    let Some(session) = active else {
+        return Paragraph::new("no file is active").dim().block(block);
+    };
+
+    let running = if session.active {
+        format!(
+            "running for {}",
+            format_duration(Duration::from_secs(session.current_secs))
+        )
+    } else {
+        "paused".to_string()
+    };
+    Paragraph::new(vec![
+        Line::from(Span::styled(
+            session.path.display().to_string(),
+            Style::default().add_modifier(Modifier::BOLD),
+        )),
+        Line::from(format!(
+            "{} in {}",
+            session.language,
+            session.project.as_deref().unwrap_or(NO_PROJECT)
+        )),
+        Line::from(running),
+        Line::from(format!(
+            "{} in {} {} since the watcher started",
+            format_duration(Duration::from_secs(session.total_secs)),
+            session.intervals.len(),
+            if session.intervals.len() == 1 {
+                "stretch"
+            } else {
+                "stretches"
+            }
+        )),
+    ])
+    .block(block)
+}
+
+fn project_lines(projects: &[(String, Duration)], total: Duration) -> Paragraph<'static> {
+    let block = Block::bordered().title(" projects today ");
+    if projects.is_empty() {
+        return Paragraph::new("nothing tracked yet").dim().block(block);
+    }
+    let lines: Vec<Line> = projects
+        .iter()
+        .take(PROJECTS)
+        .map(|(name, duration)| Line::from(bar_line(name, *duration, total)))
+        .collect();
+    Paragraph::new(lines).block(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manager::SessionStatus;
+    use std::path::PathBuf;
+
+    fn session(project: Option<&str>, active: bool, current_secs: u64) -> SessionStatus {
+        SessionStatus {
+            path: PathBuf::from("/app/src/main.rs"),
+            language: "Rust".to_string(),
+            project: project.map(str::to_string),
+            active,
+            current_secs,
+            total_secs: current_secs,
+            intervals: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn running_session_counts_towards_today_and_its_project() {
+        let mut today = DailyStats::new("2024-05-01".to_string());
+        today.total_time = Duration::from_secs(100);
+        today
+            .entries_by_project
+            .insert("app".to_string(), Duration::from_secs(60));
+        let snapshot = Snapshot {
+            status: ManagerStatus {
+                paused: false,
+                active_file: Some(PathBuf::from("/app/src/main.rs")),
+                sessions: vec![session(Some("app"), true, 30), session(None, false, 0)],
+            },
+            today,
+            activity: None,
+            now: SystemTime::now(),
+        };
+
+        assert_eq!(snapshot.today_total(), Duration::from_secs(130));
+        assert_eq!(
+            snapshot.projects(),
+            [
+                ("app".to_string(), Duration::from_secs(90)),
+                (NO_PROJECT.to_string(), Duration::from_secs(40)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use anyhow::{Context, Result};
+use axum::{Json, Router, extract::State, http::StatusCode, routing::post};
+use serde::{Deserialize, Serialize};
+use std::{
+    net::SocketAddr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{net::TcpListener, sync::mpsc::UnboundedSender};
+use tracing::{debug, error, info, instrument};
+
+use crate::{clock::SharedClock, language::Language, tracking_event::TrackingEvents};
+
+/// heartbeats older than this are accepted but not tracked. wakatime-cli
+/// queues heartbeats while offline and sends them later, but sessions only
+/// follow what happens now
+const MAX_HEARTBEAT_AGE: Duration = Duration::from_secs(5 * 60);
+
+/// a heartbeat as sent by wakatime plugins (through wakatime-cli).
+/// fields that aren't needed here are ignored
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WakaHeartbeat {
+    /// the file path for heartbeats of type file
+    pub entity: String,
+    /// file, app or domain
+    #[serde(rename = "type", default = "file_type")]
+    pub kind: String,
+    #[serde(default)]
+    pub category: Option<String>,
+    /// seconds since unix epoch
+    pub time: f64,
+    #[serde(default)]
+    pub project: Option<String>,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub is_write: bool,
+}
+
+fn file_type() -> String {
+    "file".to_string()
+}
+
+/// what a single heartbeat is answered with
+#[derive(Debug, Clone, Serialize)]
+struct HeartbeatReply {
+    data: WakaHeartbeat,
+}
+
+/// the bulk endpoint answers every heartbeat with its body and status
+#[derive(Debug, Clone, Serialize)]
+struct BulkReply {
+    responses: Vec<(HeartbeatReply, u16)>,
+}
+
+#[derive(Debug, Clone)]
+struct ApiState {
+    tracking_sender: UnboundedSender<TrackingEvents>,
+    clock: SharedClock,
+}
+
+/// starts listening on the given address. the returned future serves
+/// heartbeats forever, turning them into FileFocused/FileModified events
+pub async fn bind(
+    address: SocketAddr,
+    tracking_sender: UnboundedSender<TrackingEvents>,
+    clock: SharedClock,
+) -> Result<impl Future<Output = ()>> {
+    let listener = TcpListener::bind(address)
+        .await
+        .with_context(|| format!("failed to listen on {address}"))?;
+    let router = Router::new()
+        .route("/api/v1/users/current/heartbeats", post(heartbeat))
+        .route("/api/v1/users/current/heartbeats.bulk", post(bulk))
+        .with_state(ApiState {
+            tracking_sender,
+            clock,
+        });
+
+    Ok(async move {
+        if let Err(e) = axum::serve(listener, router).await {
+            error!("heartbeat api failed: {e}");
+        }
+    })
+}
+
+async fn heartbeat(
+    State(state): State<ApiState>,
+    Json(heartbeat): Json<WakaHeartbeat>,
+) -> (StatusCode, Json<HeartbeatReply>) {
+    let status = state.handle(&heartbeat);
+    (status, Json(HeartbeatReply { data: heartbeat }))
+}
+
+async fn bulk(
+    State(state): State<ApiState>,
+    Json(heartbeats): Json<Vec<WakaHeartbeat>>,
+) -> (StatusCode, Json<BulkReply>) {
+    let responses = heartbeats
+        .into_iter()
+        .map(|heartbeat| {
+            let status = state.handle(&heartbeat);
+            (HeartbeatReply { data: heartbeat }, status.as_u16())
+        })
+        .collect();
+    (StatusCode::CREATED, Json(BulkReply { responses }))
+}
+
+impl ApiState {
+    /// forwards the heartbeat to the manager. heartbeats that aren't
+    /// tracked are still accepted so that plugins don't send them again
+    #[instrument(name = "heartbeat_api", skip_all)]
+    fn handle(&self, heartbeat: &WakaHeartbeat) -> StatusCode {
+        if heartbeat.entity.is_empty() {
+            return StatusCode::BAD_REQUEST;
+        }
+        if let Some(event) = to_event(heartbeat, self.clock.now()) {
+            // the manager being gone means we are shutting down
+            let _ = self.tracking_sender.send(event);
+        }
+        StatusCode::CREATED
+    }
+}
+
+/// turns a file heartbeat into a FileModified event if it was a save and a
+/// FileFocused event otherwise. heartbeats of apps and domains and old ones
+/// give nothing
+fn to_event(heartbeat: &WakaHeartbeat, now: SystemTime) -> Option<TrackingEvents> {
+    if heartbeat.kind != "file" {
+        return None;
+    }
+    let time = UNIX_EPOCH.checked_add(Duration::try_from_secs_f64(heartbeat.time).ok()?)?;
+    if now.duration_since(time).unwrap_or(Duration::ZERO) > MAX_HEARTBEAT_AGE {
+        debug!(entity = heartbeat.entity, "skipped old heartbeat");
+        return None;
+    }
+
+    // plugins use names like "rust" or "C++", sessions use the registry's
+    let language = heartbeat.language.as_deref().map(|language| {
+        Language::from_alias(language)
+            .unwrap_or_else(|| Language::from_name(language))
+            .name()
+            .to_string()
+    });
+    let path = heartbeat.entity.clone();
+    let project = heartbeat
+        .project
+        .clone()
+        .filter(|project| !project.is_empty());
+
+    info!(
+        write = heartbeat.is_write,
+        "editor heartbeat: {path} ({})",
+        language.as_deref().unwrap_or("unknown language")
+    );
+    Some(if heartbeat.is_write {
+        TrackingEvents::FileModified {
+            path,
+            language,
+            project,
+        }
+    } else {
+        TrackingEvents::FileFocused {
+            path,
+            language,
+            project,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, MockClock};
+    use serde_json::json;
+
+    /// a file heartbeat sent at the given time, as wakatime-cli sends them
+    fn heartbeat(time: SystemTime) -> WakaHeartbeat {
+        let time = time.duration_since(UNIX_EPOCH).unwrap().as_secs_f64();
+        serde_json::from_value(json!({
+            "entity": "/work/src/main.rs",
+            "type": "file",
+            "category": "coding",
+            "time": time,
+            "project": "work",
+            "language": "Rust",
+            "lines": 120,
+            "is_write": false,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn saves_are_modifications_and_the_rest_focus() {
+        let now = MockClock::new().now();
+        let mut beat = heartbeat(now);
+
+        let Some(TrackingEvents::FileFocused {
+            path,
+            language,
+            project,
+        }) = to_event(&beat, now)
+        else {
+            panic!("expected a focus");
+        };
+        assert_eq!(path, "/work/src/main.rs");
+        assert_eq!(language.as_deref(), Some("Rust"));
+        assert_eq!(project.as_deref(), Some("work"));
+
+        beat.is_write = true;
+        assert!(matches!(
+            to_event(&beat, now),
+            Some(TrackingEvents::FileModified { .. })
+        ));
+    }
+
+    #[test]
+    fn only_files_are_tracked() {
+        let now = MockClock::new().now();
+        let mut beat = heartbeat(now);
+        for kind in ["app", "domain", "url"] {
+            beat.kind = kind.to_string();
+            assert!(to_event(&beat, now).is_none(), "{kind}");
+        }
+
+        // heartbeats without a type are files
+        let beat: WakaHeartbeat =
+            serde_json::from_value(json!({"entity": "/work/main.rs", "time": 1.0})).unwrap();
+        assert_eq!(beat.kind, "file");
+    }
+
+    #[test]
+    fn old_heartbeats_are_skipped() {
+        let now = MockClock::new().now();
+
+        assert!(to_event(&heartbeat(now - MAX_HEARTBEAT_AGE), now).is_some());
+        let queued = now - MAX_HEARTBEAT_AGE - Duration::from_secs(1);
+        assert!(to_event(&heartbeat(queued), now).is_none());
+        // a clock a little ahead of ours
+        assert!(to_event(&heartbeat(now + Duration::from_secs(3)), now).is_some());
+        // times that aren't times at all
+        let mut beat = heartbeat(now);
+        beat.time = -1.0;
+        assert!(to_event(&beat, now).is_none());
+    }
+
+    #[test]
+    fn languages_of_plugins_are_mapped() {
+        let now = MockClock::new().now();
+        let cases = [
+            (Some("rust"), Some("Rust")),
+            (Some("C++"), Some("C++")),
+            (Some("cpp"), Some("C++")),
+            (Some("sh"), Some("Bash")),
+            // languages we don't know keep their name
+            (Some("Zig"), Some("Zig")),
+            (None, None),
+        ];
+        for (sent, expected) in cases {
+            let mut beat = heartbeat(now);
+            beat.language = sent.map(str::to_string);
+            let Some(TrackingEvents::FileFocused { language, .. }) = to_event(&beat, now) else {
+                panic!("expected a focus");
+            };
+            assert_eq!(language.as_deref(), expected, "{sent:?}");
+        }
+
+        let mut beat = heartbeat(now);
+        beat.project = Some(String::new());
+        let Some(TrackingEvents::FileFocused { project, .. }) = to_event(&beat, now) else {
+            panic!("expected a focus");
+        };
+        assert_eq!(project, None);
+    }
+
+    #[tokio::test]
+    async fn bulk_answers_every_heartbeat() {
+        let clock = MockClock::new();
+        let (tracking_tx, mut tracking_rx) = tokio::sync::mpsc::unbounded_channel();
+        let state = ApiState {
+            tracking_sender: tracking_tx,
+            clock: clock.shared(),
+        };
+        let tracked = heartbeat(clock.now());
+        let mut empty = heartbeat(clock.now());
+        empty.entity = String::new();
+
+        let (status, Json(reply)) = bulk(State(state), Json(vec![tracked, empty])).await;
+
+        assert_eq!(status, StatusCode::CREATED);
+        let reply = serde_json::to_value(reply).unwrap();
+        let responses = reply["responses"].as_array().unwrap();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0][0]["data"]["entity"], "/work/src/main.rs");
+        assert_eq!(responses[0][1], 201);
+        assert_eq!(responses[1][0]["data"]["entity"], "");
+        assert_eq!(responses[1][1], 400);
+
+        assert!(matches!(
+            tracking_rx.try_recv(),
+            Ok(TrackingEvents::FileFocused { .. })
+        ));
+        assert!(tracking_rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,152 @@
+//! runs the watcher and stops it with signals, like a terminal or a
+//! service manager would
+
+use std::{
+    io::Write,
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+const BIN: &str = env!("CARGO_BIN_EXE_wakatime");
+
+/// a running watcher with a data directory of its own
+struct Watcher {
+    child: Child,
+    root: PathBuf,
+}
+
+impl Watcher {
+    fn start(name: &str) -> Self {
+        let root = std::env::temp_dir().join(format!("timeit-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("project")).unwrap();
+
+        let child = Command::new(BIN)
+            .arg("--data-dir")
+            .arg(root.join("data"))
+            // missing, so every setting is at its default
+            .arg("--config")
+            .arg(root.join("config.toml"))
+            .arg("watch")
+            .arg(root.join("project"))
+            .env_remove("TIMEIT_LOG_DIR")
+            .env("TIMEIT_LOG", "warn")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let watcher = Self { child, root };
+
+        let started = Instant::now();
+        while !(watcher.socket("timeit.sock").exists() && watcher.socket("editor.sock").exists()) {
+            assert!(
+                started.elapsed() < Duration::from_secs(10),
+                "the watcher didn't start"
+            );
+            thread::sleep(Duration::from_millis(50));
+        }
+        watcher
+    }
+
+    fn socket(&self, name: &str) -> PathBuf {
+        self.root.join("data").join(name)
+    }
+
+    /// tells the watcher that a file is being edited, like an editor plugin
+    fn focus(&self, file: &str) -> PathBuf {
+        let path = self.root.join("project").join(file);
+        let mut stream = UnixStream::connect(self.socket("editor.sock")).unwrap();
+        writeln!(
+            stream,
+            r#"{{"event": "focus", "path": "{}"}}"#,
+            path.display()
+        )
+        .unwrap();
+        path
+    }
+
+    fn signal(&self, signal: &str) {
+        let status = Command::new("kill")
+            .arg(format!("-{signal}"))
+            .arg(self.child.id().to_string())
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    fn wait(&mut self) -> ExitStatus {
+        let started = Instant::now();
+        loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return status;
+            }
+            assert!(
+                started.elapsed() < Duration::from_secs(10),
+                "the watcher didn't stop"
+            );
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    /// the saved entries, as exported
+    fn entries(&self) -> Vec<serde_json::Value> {
+        let output = Command::new(BIN)
+            .arg("--data-dir")
+            .arg(self.root.join("data"))
+            .args(["export", "--format", "json"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let export: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        export["entries"].as_array().unwrap().clone()
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+fn assert_saved(watcher: &Watcher, path: &Path, at_least_secs: u64) {
+    let entries = watcher.entries();
+    assert_eq!(entries.len(), 1, "{entries:?}");
+    assert_eq!(entries[0]["path"], path.to_str().unwrap());
+    assert!(entries[0]["duration_secs"].as_u64().unwrap() >= at_least_secs);
+}
+
+#[test]
+fn stopping_saves_the_running_session() {
+    for signal in ["TERM", "INT"] {
+        let mut watcher = Watcher::start(&format!("stop-{signal}"));
+        let path = watcher.focus("main.rs");
+        thread::sleep(Duration::from_millis(1500));
+
+        watcher.signal(signal);
+        assert!(watcher.wait().success(), "SIG{signal}");
+        assert_saved(&watcher, &path, 1);
+        // the next watcher doesn't have to clean up after this one
+        assert!(!watcher.socket("timeit.sock").exists());
+        assert!(!watcher.socket("editor.sock").exists());
+    }
+}
+
+#[test]
+fn hangup_reloads_without_stopping() {
+    let mut watcher = Watcher::start("hangup");
+    let path = watcher.focus("lib.rs");
+    thread::sleep(Duration::from_millis(500));
+
+    watcher.signal("HUP");
+    thread::sleep(Duration::from_millis(1000));
+    assert!(watcher.child.try_wait().unwrap().is_none());
+
+    watcher.signal("TERM");
+    assert!(watcher.wait().success());
+    assert_saved(&watcher, &path, 1);
+}